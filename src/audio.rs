@@ -0,0 +1,974 @@
+use std::collections::HashMap;
+use std::convert::TryInto;
+use std::fs::File;
+use std::io::{BufReader, Read, Seek, SeekFrom};
+use std::path::Path;
+
+use anyhow::Context;
+
+use crate::parse::{ChannelsDef, OffsetDef};
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum SampleFormat {
+    Wav,
+    Aiff,
+    Flac,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct SampleHeader {
+    pub format: SampleFormat,
+    pub channel_count: u16,
+    pub sampling_rate: u32,
+    pub bits_per_sample: u16,
+}
+
+/// Whether the file extension is one of a supported sample format
+pub fn is_sample_file(file_path: &Path) -> bool {
+    let extension = file_path
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(str::to_lowercase);
+    matches!(
+        extension.as_deref(),
+        Some("wav") | Some("wave") | Some("aif") | Some("aiff") | Some("aifc") | Some("flac")
+    )
+}
+
+/// Reads an audio file into interleaved 16 bit samples, the format is detected from the file header
+pub fn read_sample(file_path: &Path) -> anyhow::Result<(SampleHeader, Vec<i16>)> {
+    let file = File::open(file_path).with_context(|| "Cannot open input file")?;
+    let mut reader = BufReader::new(file);
+    let format = detect_format(&mut reader)?;
+    let (header, bit_depth) = match format {
+        SampleFormat::Wav => read_wav(&mut reader),
+        SampleFormat::Aiff => read_aiff(&mut reader),
+        SampleFormat::Flac => read_flac(reader),
+    }
+    .with_context(|| format!("Cannot read {:?} input file", format))?;
+    let data = convert_bit_depth(bit_depth)?;
+    Ok((header, data))
+}
+
+/// Reads the header and the number of frames of an audio file without decoding its sample data
+pub fn read_sample_info(file_path: &Path) -> anyhow::Result<(SampleHeader, u64)> {
+    let file = File::open(file_path).with_context(|| "Cannot open input file")?;
+    let mut reader = BufReader::new(file);
+    let format = detect_format(&mut reader)?;
+    match format {
+        SampleFormat::Wav => read_wav_info(&mut reader),
+        SampleFormat::Aiff => read_aiff_info(&mut reader),
+        SampleFormat::Flac => {
+            let flac = claxon::FlacReader::new(reader)?;
+            let info = flac.streaminfo();
+            let frames = info
+                .samples
+                .ok_or_else(|| anyhow::anyhow!("Unknown number of frames"))?;
+            Ok((flac_header(&info), frames))
+        }
+    }
+    .with_context(|| format!("Cannot read {:?} input file", format))
+}
+
+fn detect_format<R: Read + Seek>(reader: &mut R) -> anyhow::Result<SampleFormat> {
+    let mut magic = [0; 12];
+    reader
+        .read_exact(&mut magic)
+        .with_context(|| "Cannot read input file header")?;
+    reader.seek(SeekFrom::Start(0))?;
+    match (&magic[0..4], &magic[8..12]) {
+        (b"RIFF", b"WAVE") => Ok(SampleFormat::Wav),
+        (b"FORM", b"AIFF") | (b"FORM", b"AIFC") => Ok(SampleFormat::Aiff),
+        (b"fLaC", _) => Ok(SampleFormat::Flac),
+        _ => Err(anyhow::anyhow!(
+            "Unknown input file format, supported formats are wav, aiff and flac"
+        )),
+    }
+}
+
+// Format tags of the wav fmt chunk, extensible files give the actual tag in their SubFormat GUID
+const WAV_FORMAT_PCM: u16 = 0x0001;
+const WAV_FORMAT_IEEE_FLOAT: u16 = 0x0003;
+const WAV_FORMAT_EXTENSIBLE: u16 = 0xfffe;
+// The part of the SubFormat GUID that follows the format tag
+const WAV_SUBFORMAT_SUFFIX: [u8; 14] = [
+    0x00, 0x00, 0x00, 0x00, 0x10, 0x00, 0x80, 0x00, 0x00, 0xaa, 0x00, 0x38, 0x9b, 0x71,
+];
+
+// A chunk of a riff or iff file, the contents are only read for the chunks that are needed
+struct Chunk {
+    size: u64,
+    contents: Vec<u8>,
+}
+
+// Reads the chunks following the 12 byte file header, chunk sizes are little endian in riff and
+// big endian in iff files. Only the contents of the `wanted` chunks are read, the rest is skipped.
+fn read_chunks<R: Read + Seek>(
+    reader: &mut R,
+    big_endian: bool,
+    wanted: &[&[u8; 4]],
+) -> anyhow::Result<HashMap<[u8; 4], Chunk>> {
+    let mut chunks = HashMap::new();
+    reader.seek(SeekFrom::Start(12))?;
+    let mut chunk_header = [0; 8];
+    // a truncated chunk header ends the file
+    while reader.read_exact(&mut chunk_header).is_ok() {
+        let id: [u8; 4] = chunk_header[0..4].try_into()?;
+        let size: [u8; 4] = chunk_header[4..8].try_into()?;
+        let size = match big_endian {
+            true => u32::from_be_bytes(size),
+            false => u32::from_le_bytes(size),
+        } as u64;
+        let mut contents = Vec::new();
+        if wanted.contains(&&id) {
+            reader.by_ref().take(size).read_to_end(&mut contents)?;
+        } else {
+            reader.seek(SeekFrom::Current(size as i64))?;
+        }
+        // chunks are padded to an even size
+        reader.seek(SeekFrom::Current((size % 2) as i64))?;
+        chunks.insert(id, Chunk { size, contents });
+    }
+    Ok(chunks)
+}
+
+// Reads the header from a wav fmt chunk, along with the format tag and the size of a frame
+fn wav_format(fmt: Option<&Chunk>) -> anyhow::Result<(SampleHeader, u16, u16)> {
+    let fmt = fmt
+        .map(|c| c.contents.as_slice())
+        .filter(|c| c.len() >= 16)
+        .ok_or_else(|| anyhow::anyhow!("Missing or invalid fmt chunk"))?;
+
+    let format_tag = u16::from_le_bytes([fmt[0], fmt[1]]);
+    let channel_count = u16::from_le_bytes([fmt[2], fmt[3]]);
+    let sampling_rate = u32::from_le_bytes(fmt[4..8].try_into()?);
+    if sampling_rate == 0 {
+        return Err(anyhow::anyhow!("Invalid sampling rate of 0 Hz"));
+    }
+    let block_align = u16::from_le_bytes([fmt[12], fmt[13]]);
+    let mut bits_per_sample = u16::from_le_bytes([fmt[14], fmt[15]]);
+    let format_tag = match format_tag {
+        WAV_FORMAT_EXTENSIBLE => {
+            let extension = fmt
+                .get(18..40)
+                .ok_or_else(|| anyhow::anyhow!("Missing wav format extension"))?;
+            let valid_bits = u16::from_le_bytes([extension[0], extension[1]]);
+            if valid_bits != 0 {
+                bits_per_sample = valid_bits;
+            }
+            let sub_format = &extension[6..22];
+            if sub_format[2..] != WAV_SUBFORMAT_SUFFIX {
+                return Err(anyhow::anyhow!(
+                    "Unsupported wav sub format {:02x?}",
+                    sub_format
+                ));
+            }
+            u16::from_le_bytes([sub_format[0], sub_format[1]])
+        }
+        tag => tag,
+    };
+    if channel_count == 0 || block_align % channel_count != 0 {
+        return Err(anyhow::anyhow!(
+            "Invalid block size {} for {} channels",
+            block_align,
+            channel_count
+        ));
+    }
+
+    let header = SampleHeader {
+        format: SampleFormat::Wav,
+        channel_count,
+        sampling_rate,
+        bits_per_sample,
+    };
+    Ok((header, format_tag, block_align))
+}
+
+fn read_wav_info<R: Read + Seek>(reader: &mut R) -> anyhow::Result<(SampleHeader, u64)> {
+    let chunks = read_chunks(reader, false, &[b"fmt "])?;
+    let (header, _, block_align) = wav_format(chunks.get(b"fmt "))?;
+    let data = chunks
+        .get(b"data")
+        .ok_or_else(|| anyhow::anyhow!("Missing data chunk"))?;
+    Ok((header, data.size / block_align as u64))
+}
+
+fn read_wav<R: Read + Seek>(reader: &mut R) -> anyhow::Result<(SampleHeader, wav::BitDepth)> {
+    let chunks = read_chunks(reader, false, &[b"fmt ", b"data"])?;
+    let (header, format_tag, block_align) = wav_format(chunks.get(b"fmt "))?;
+    let data = &chunks
+        .get(b"data")
+        .ok_or_else(|| anyhow::anyhow!("Missing data chunk"))?
+        .contents;
+    let channel_count = header.channel_count;
+    let bits_per_sample = header.bits_per_sample;
+
+    // samples are stored in containers of block_align / channel_count bytes, valid bits first
+    let bit_depth = match (format_tag, block_align / channel_count) {
+        (WAV_FORMAT_PCM, 1) => wav::BitDepth::Eight(data.to_vec()),
+        (WAV_FORMAT_PCM, 2) => wav::BitDepth::Sixteen(
+            data.chunks_exact(2)
+                .map(|c| i16::from_le_bytes([c[0], c[1]]))
+                .collect(),
+        ),
+        (WAV_FORMAT_PCM, 3) => wav::BitDepth::TwentyFour(
+            data.chunks_exact(3)
+                .map(|c| i32::from_le_bytes([0, c[0], c[1], c[2]]))
+                .collect(),
+        ),
+        // 32 bit samples only keep their upper bytes when converted to 16 bit anyway
+        (WAV_FORMAT_PCM, 4) => wav::BitDepth::TwentyFour(
+            data.chunks_exact(4)
+                .map(|c| i32::from_le_bytes([c[0], c[1], c[2], c[3]]))
+                .collect(),
+        ),
+        (WAV_FORMAT_IEEE_FLOAT, 4) => wav::BitDepth::ThirtyTwoFloat(
+            data.chunks_exact(4)
+                .map(|c| f32::from_le_bytes([c[0], c[1], c[2], c[3]]))
+                .collect(),
+        ),
+        (WAV_FORMAT_IEEE_FLOAT, 8) => wav::BitDepth::ThirtyTwoFloat(
+            data.chunks_exact(8)
+                .map(|c| f64::from_le_bytes(c.try_into().unwrap()) as f32)
+                .collect(),
+        ),
+        _ => {
+            return Err(anyhow::anyhow!(
+                "Unsupported wav format {:#06x} with {} bits per sample",
+                format_tag,
+                bits_per_sample
+            ))
+        }
+    };
+
+    Ok((header, bit_depth))
+}
+
+// Reads the header from an aiff COMM chunk, along with the compression type and the number of
+// frames
+fn aiff_format(comm: Option<&Chunk>, aifc: bool) -> anyhow::Result<(SampleHeader, &[u8], u64)> {
+    let comm = comm
+        .map(|c| c.contents.as_slice())
+        .filter(|c| c.len() >= 18)
+        .ok_or_else(|| anyhow::anyhow!("Missing or invalid COMM chunk"))?;
+
+    let channel_count = u16::from_be_bytes([comm[0], comm[1]]);
+    let frames = u32::from_be_bytes(comm[2..6].try_into()?) as u64;
+    let bits_per_sample = u16::from_be_bytes([comm[6], comm[7]]);
+    let sampling_rate = extended_to_f64(comm[8..18].try_into()?).round() as u32;
+    if sampling_rate == 0 {
+        return Err(anyhow::anyhow!("Invalid sampling rate of 0 Hz"));
+    }
+    let compression = match aifc {
+        true => comm
+            .get(18..22)
+            .ok_or_else(|| anyhow::anyhow!("Missing AIFC compression type"))?,
+        false => b"NONE",
+    };
+
+    let header = SampleHeader {
+        format: SampleFormat::Aiff,
+        channel_count,
+        sampling_rate,
+        bits_per_sample,
+    };
+    Ok((header, compression, frames))
+}
+
+fn is_aifc<R: Read>(reader: &mut R) -> anyhow::Result<bool> {
+    let mut file_header = [0; 12];
+    reader.read_exact(&mut file_header)?;
+    Ok(&file_header[8..12] == b"AIFC")
+}
+
+fn read_aiff_info<R: Read + Seek>(reader: &mut R) -> anyhow::Result<(SampleHeader, u64)> {
+    let aifc = is_aifc(reader)?;
+    let chunks = read_chunks(reader, true, &[b"COMM"])?;
+    let (header, _, frames) = aiff_format(chunks.get(b"COMM"), aifc)?;
+    if !chunks.contains_key(b"SSND") {
+        return Err(anyhow::anyhow!("Missing or invalid SSND chunk"));
+    }
+    Ok((header, frames))
+}
+
+fn read_aiff<R: Read + Seek>(reader: &mut R) -> anyhow::Result<(SampleHeader, wav::BitDepth)> {
+    let aifc = is_aifc(reader)?;
+    let chunks = read_chunks(reader, true, &[b"COMM", b"SSND"])?;
+    let (header, compression, _) = aiff_format(chunks.get(b"COMM"), aifc)?;
+    let bits_per_sample = header.bits_per_sample;
+    let ssnd = chunks
+        .get(b"SSND")
+        .map(|c| c.contents.as_slice())
+        .filter(|c| c.len() >= 8)
+        .ok_or_else(|| anyhow::anyhow!("Missing or invalid SSND chunk"))?;
+    let offset = u32::from_be_bytes(ssnd[0..4].try_into()?) as usize;
+    let data = ssnd
+        .get(8 + offset..)
+        .ok_or_else(|| anyhow::anyhow!("Invalid SSND chunk offset"))?;
+
+    // samples are left aligned in as many bytes as needed
+    let bit_depth = match (compression, bits_per_sample.div_ceil(8)) {
+        // 8 bit aiff data is signed, wav data is unsigned
+        (b"NONE", 1) | (b"twos", 1) => {
+            wav::BitDepth::Eight(data.iter().map(|&b| b ^ 0x80).collect())
+        }
+        (b"NONE", 2) | (b"twos", 2) => wav::BitDepth::Sixteen(
+            data.chunks_exact(2)
+                .map(|c| i16::from_be_bytes([c[0], c[1]]))
+                .collect(),
+        ),
+        (b"sowt", 2) => wav::BitDepth::Sixteen(
+            data.chunks_exact(2)
+                .map(|c| i16::from_le_bytes([c[0], c[1]]))
+                .collect(),
+        ),
+        (b"NONE", 3) | (b"twos", 3) => wav::BitDepth::TwentyFour(
+            data.chunks_exact(3)
+                .map(|c| i32::from_be_bytes([c[0], c[1], c[2], 0]))
+                .collect(),
+        ),
+        // 32 bit samples only keep their upper bytes when converted to 16 bit anyway
+        (b"NONE", 4) | (b"twos", 4) => wav::BitDepth::TwentyFour(
+            data.chunks_exact(4)
+                .map(|c| i32::from_be_bytes([c[0], c[1], c[2], c[3]]))
+                .collect(),
+        ),
+        (b"fl32", 4) | (b"FL32", 4) => wav::BitDepth::ThirtyTwoFloat(
+            data.chunks_exact(4)
+                .map(|c| f32::from_be_bytes([c[0], c[1], c[2], c[3]]))
+                .collect(),
+        ),
+        _ => {
+            return Err(anyhow::anyhow!(
+                "Unsupported aiff compression '{}' with {} bits per sample",
+                String::from_utf8_lossy(compression),
+                bits_per_sample
+            ))
+        }
+    };
+
+    Ok((header, bit_depth))
+}
+
+// Converts an 80 bit IEEE 754 extended precision float, used for the aiff sampling rate
+fn extended_to_f64(bytes: [u8; 10]) -> f64 {
+    let exponent = (u16::from_be_bytes([bytes[0], bytes[1]]) & 0x7fff) as i32;
+    let mantissa = u64::from_be_bytes(bytes[2..10].try_into().unwrap());
+    let sign = if bytes[0] & 0x80 != 0 { -1.0 } else { 1.0 };
+    sign * mantissa as f64 * 2f64.powi(exponent - 16383 - 63)
+}
+
+fn read_flac<R: Read>(reader: R) -> anyhow::Result<(SampleHeader, wav::BitDepth)> {
+    let mut flac = claxon::FlacReader::new(reader)?;
+    let info = flac.streaminfo();
+    let bits = info.bits_per_sample;
+    let samples = flac.samples().collect::<Result<Vec<i32>, _>>()?;
+
+    // scale samples up so they can be handled like wav data of the same size
+    let bit_depth = match bits {
+        1..=8 => wav::BitDepth::Eight(
+            samples
+                .into_iter()
+                .map(|s| ((s << (8 - bits)) + 128) as u8)
+                .collect(),
+        ),
+        9..=16 => wav::BitDepth::Sixteen(
+            samples
+                .into_iter()
+                .map(|s| (s << (16 - bits)) as i16)
+                .collect(),
+        ),
+        _ => wav::BitDepth::TwentyFour(samples.into_iter().map(|s| s << (32 - bits)).collect()),
+    };
+
+    Ok((flac_header(&info), bit_depth))
+}
+
+fn flac_header(info: &claxon::metadata::StreamInfo) -> SampleHeader {
+    SampleHeader {
+        format: SampleFormat::Flac,
+        channel_count: info.channels as u16,
+        sampling_rate: info.sample_rate,
+        bits_per_sample: info.bits_per_sample as u16,
+    }
+}
+
+/// Converts sample data of any supported bit depth into 16 bit samples
+fn convert_bit_depth(bit_depth: wav::BitDepth) -> anyhow::Result<Vec<i16>> {
+    match bit_depth {
+        // 8 bit wav data is unsigned, centered around 128
+        wav::BitDepth::Eight(d) => Ok(d.into_iter().map(|s| (s as i16 - 128) << 8).collect()),
+        wav::BitDepth::Sixteen(d) => Ok(d),
+        // 24 bit samples are stored in the upper three bytes of an i32
+        wav::BitDepth::TwentyFour(d) => Ok(d.into_iter().map(|s| (s >> 16) as i16).collect()),
+        wav::BitDepth::ThirtyTwoFloat(d) => Ok(d
+            .into_iter()
+            .map(|s| (s.clamp(-1.0, 1.0) * i16::MAX as f32).round() as i16)
+            .collect()),
+        wav::BitDepth::Empty => Err(anyhow::anyhow!("Input file contains no sample data")),
+    }
+}
+
+/// Turns interleaved sample data with `channel_count` channels into mono
+pub fn to_mono(
+    data: Vec<i16>,
+    channel_count: u16,
+    channels: ChannelsDef,
+) -> anyhow::Result<Vec<i16>> {
+    let channel_count = channel_count as usize;
+    match channel_count {
+        0 => return Err(anyhow::anyhow!("Input file has no channels")),
+        1 if channels == ChannelsDef::Side => {
+            return Err(anyhow::anyhow!(
+                "Cannot extract side channel from mono input"
+            ))
+        }
+        1 => return Ok(data),
+        _ => {}
+    }
+
+    let frames = data.chunks_exact(channel_count);
+    let mono = match channels {
+        ChannelsDef::Mix => frames
+            .map(|f| (f.iter().map(|&s| s as i32).sum::<i32>() / channel_count as i32) as i16)
+            .collect(),
+        ChannelsDef::Left => frames.map(|f| f[0]).collect(),
+        ChannelsDef::Right => frames.map(|f| f[1]).collect(),
+        ChannelsDef::Mid => frames
+            .map(|f| ((f[0] as i32 + f[1] as i32) / 2) as i16)
+            .collect(),
+        ChannelsDef::Side => frames
+            .map(|f| ((f[0] as i32 - f[1] as i32) / 2) as i16)
+            .collect(),
+    };
+    Ok(mono)
+}
+
+/// Cuts the region between `start` and `end` out of mono sample data
+pub fn trim_region(
+    data: Vec<i16>,
+    sampling_rate: u32,
+    start: Option<OffsetDef>,
+    end: Option<OffsetDef>,
+) -> anyhow::Result<Vec<i16>> {
+    let start_frame = start.map_or(0, |o| offset_to_frame(o, sampling_rate));
+    let end_frame = end.map_or(data.len(), |o| offset_to_frame(o, sampling_rate));
+    if end_frame > data.len() {
+        return Err(anyhow::anyhow!(
+            "End offset at frame {} is past the end of the sample ({} frames)",
+            end_frame,
+            data.len()
+        ));
+    }
+    if start_frame >= end_frame {
+        return Err(anyhow::anyhow!(
+            "Sample region from frame {} to frame {} is empty",
+            start_frame,
+            end_frame
+        ));
+    }
+    Ok(data[start_frame..end_frame].to_vec())
+}
+
+/// Splits mono sample data into `count` equally long slices
+pub fn slice_equal(data: &[i16], count: u32) -> anyhow::Result<Vec<Vec<i16>>> {
+    let count = count as usize;
+    if count == 0 || count > data.len() {
+        return Err(anyhow::anyhow!(
+            "Cannot split {} frames into {} slices",
+            data.len(),
+            count
+        ));
+    }
+    Ok((0..count)
+        .map(|i| data[i * data.len() / count..(i + 1) * data.len() / count].to_vec())
+        .collect())
+}
+
+/// Splits mono sample data into slices, cutting at each of the `markers`
+pub fn slice_at(
+    data: &[i16],
+    sampling_rate: u32,
+    markers: &[OffsetDef],
+) -> anyhow::Result<Vec<Vec<i16>>> {
+    let mut bounds = vec![0];
+    for marker in markers {
+        let frame = offset_to_frame(*marker, sampling_rate);
+        if frame <= *bounds.last().unwrap() || frame >= data.len() {
+            return Err(anyhow::anyhow!(
+                "Slice marker {:?} must come after the previous marker and before the end of the sample",
+                marker
+            ));
+        }
+        bounds.push(frame);
+    }
+    bounds.push(data.len());
+    Ok(bounds
+        .windows(2)
+        .map(|b| data[b[0]..b[1]].to_vec())
+        .collect())
+}
+
+fn offset_to_frame(offset: OffsetDef, sampling_rate: u32) -> usize {
+    match offset {
+        OffsetDef::Frames(frames) => frames as usize,
+        OffsetDef::Seconds(seconds) => (seconds * sampling_rate as f64).round() as usize,
+        OffsetDef::Milliseconds(ms) => (ms * sampling_rate as f64 / 1000.0).round() as usize,
+    }
+}
+
+/// Removes leading and trailing audio quieter than `threshold` dBFS
+pub fn trim_silence(data: Vec<i16>, threshold: f64) -> anyhow::Result<Vec<i16>> {
+    if threshold > 0.0 {
+        return Err(anyhow::anyhow!(
+            "Silence threshold must be at most 0 dBFS, got {}",
+            threshold
+        ));
+    }
+    let limit = db_to_amplitude(threshold);
+    let audible = |s: &i16| (*s as f64).abs() > limit;
+    match (
+        data.iter().position(audible),
+        data.iter().rposition(audible),
+    ) {
+        (Some(first), Some(last)) => Ok(data[first..=last].to_vec()),
+        _ => Err(anyhow::anyhow!(
+            "Sample is silent below the threshold of {} dBFS",
+            threshold
+        )),
+    }
+}
+
+/// Whether no sample value of the data is above `threshold` dBFS
+pub fn is_silent(data: &[i16], threshold: f64) -> bool {
+    let limit = db_to_amplitude(threshold);
+    data.iter().all(|s| (*s as f64).abs() <= limit)
+}
+
+/// Scales sample data so that its peak reaches `peak` dBFS
+pub fn normalize(data: Vec<i16>, peak: f64) -> anyhow::Result<Vec<i16>> {
+    if peak > 0.0 {
+        return Err(anyhow::anyhow!(
+            "Normalization peak must be at most 0 dBFS, got {}",
+            peak
+        ));
+    }
+    let current = data.iter().map(|&s| (s as f64).abs()).fold(0.0, f64::max);
+    if current == 0.0 {
+        return Ok(data);
+    }
+    let gain = db_to_amplitude(peak) / current;
+    Ok(data
+        .into_iter()
+        .map(|s| {
+            (s as f64 * gain)
+                .round()
+                .clamp(i16::MIN as f64, i16::MAX as f64) as i16
+        })
+        .collect())
+}
+
+fn db_to_amplitude(db: f64) -> f64 {
+    10f64.powf(db / 20.0) * i16::MAX as f64
+}
+
+// Number of sinc zero crossings on each side of the resampling filter
+const RESAMPLE_ZERO_CROSSINGS: f64 = 32.0;
+
+/// Resamples mono sample data using a band-limited (windowed sinc) interpolation filter.
+/// When downsampling, the filter cutoff is lowered to the new nyquist frequency so that
+/// content above it is removed instead of aliasing.
+pub fn resample(data: &[i16], from_rate: u32, to_rate: u32) -> anyhow::Result<Vec<i16>> {
+    if from_rate == 0 || to_rate == 0 {
+        return Err(anyhow::anyhow!(
+            "Cannot resample from {} Hz to {} Hz",
+            from_rate,
+            to_rate
+        ));
+    }
+    if from_rate == to_rate || data.is_empty() {
+        return Ok(data.to_vec());
+    }
+
+    let ratio = to_rate as f64 / from_rate as f64;
+    // leave some room for the transition band below nyquist
+    let cutoff = ratio.min(1.0) * 0.95;
+    let half_width = RESAMPLE_ZERO_CROSSINGS / cutoff;
+    let out_len = (data.len() as f64 * ratio).ceil() as usize;
+
+    let resampled = (0..out_len)
+        .map(|n| {
+            let t = n as f64 / ratio;
+            let first = (t - half_width).ceil().max(0.0) as usize;
+            let last = ((t + half_width).floor() as usize).min(data.len() - 1);
+            let value: f64 = (first..=last)
+                .map(|k| {
+                    let x = t - k as f64;
+                    data[k] as f64 * cutoff * sinc(cutoff * x) * blackman(x / half_width)
+                })
+                .sum();
+            value.round().clamp(i16::MIN as f64, i16::MAX as f64) as i16
+        })
+        .collect();
+    Ok(resampled)
+}
+
+fn sinc(x: f64) -> f64 {
+    if x == 0.0 {
+        1.0
+    } else {
+        let x = std::f64::consts::PI * x;
+        x.sin() / x
+    }
+}
+
+// Blackman window over -1..=1
+fn blackman(x: f64) -> f64 {
+    let x = std::f64::consts::PI * x;
+    0.42 + 0.5 * x.cos() + 0.08 * (2.0 * x).cos()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn test_convert_bit_depth() -> anyhow::Result<()> {
+        let eight = convert_bit_depth(wav::BitDepth::Eight(vec![0, 128, 255]))?;
+        assert_eq!(eight, vec![i16::MIN, 0, 127 << 8]);
+
+        let twenty_four =
+            convert_bit_depth(wav::BitDepth::TwentyFour(vec![i32::MIN, 0, 0x7fffff << 8]))?;
+        assert_eq!(twenty_four, vec![i16::MIN, 0, i16::MAX]);
+
+        let float = convert_bit_depth(wav::BitDepth::ThirtyTwoFloat(vec![-2.0, 0.0, 0.5, 1.0]))?;
+        assert_eq!(float, vec![-i16::MAX, 0, 16384, i16::MAX]);
+
+        assert!(convert_bit_depth(wav::BitDepth::Empty).is_err());
+        Ok(())
+    }
+
+    fn aiff(form_type: &[u8], comm: &[u8], sound_data: &[u8]) -> Vec<u8> {
+        let mut ssnd = vec![0; 8];
+        ssnd.extend_from_slice(sound_data);
+        let mut body = form_type.to_vec();
+        for (id, chunk) in [(b"COMM", comm), (b"SSND", ssnd.as_slice())].iter() {
+            body.extend_from_slice(*id);
+            body.extend_from_slice(&(chunk.len() as u32).to_be_bytes());
+            body.extend_from_slice(chunk);
+        }
+        let mut bytes = b"FORM".to_vec();
+        bytes.extend_from_slice(&(body.len() as u32).to_be_bytes());
+        bytes.extend(body);
+        bytes
+    }
+
+    #[test]
+    fn test_read_aiff() -> anyhow::Result<()> {
+        // 2 channels, 2 frames, 16 bits, 44100 Hz
+        let comm = [
+            0, 2, 0, 0, 0, 2, 0, 16, 0x40, 0x0e, 0xac, 0x44, 0, 0, 0, 0, 0, 0,
+        ];
+        let bytes = aiff(
+            b"AIFF",
+            &comm,
+            &[0x7f, 0xff, 0x80, 0x00, 0x00, 0x01, 0xff, 0xff],
+        );
+        let mut reader = Cursor::new(bytes);
+        assert_eq!(detect_format(&mut reader)?, SampleFormat::Aiff);
+
+        let (header, bit_depth) = read_aiff(&mut reader)?;
+        assert_eq!(
+            header,
+            SampleHeader {
+                format: SampleFormat::Aiff,
+                channel_count: 2,
+                sampling_rate: 44100,
+                bits_per_sample: 16,
+            }
+        );
+        assert_eq!(
+            convert_bit_depth(bit_depth)?,
+            vec![i16::MAX, i16::MIN, 1, -1]
+        );
+        reader.set_position(0);
+        assert_eq!(read_aiff_info(&mut reader)?, (header, 2));
+
+        // little endian aifc
+        let mut comm = comm.to_vec();
+        comm.extend_from_slice(b"sowt\0\0");
+        let bytes = aiff(
+            b"AIFC",
+            &comm,
+            &[0xff, 0x7f, 0x00, 0x80, 0x01, 0x00, 0xff, 0xff],
+        );
+        let (_, bit_depth) = read_aiff(&mut Cursor::new(bytes))?;
+        assert_eq!(
+            convert_bit_depth(bit_depth)?,
+            vec![i16::MAX, i16::MIN, 1, -1]
+        );
+
+        // 0 Hz sampling rate
+        comm[8..18].fill(0);
+        let bytes = aiff(b"AIFC", &comm, &[0, 0]);
+        assert!(read_aiff(&mut Cursor::new(bytes)).is_err());
+        Ok(())
+    }
+
+    fn wav(fmt: &[u8], data: &[u8]) -> Vec<u8> {
+        let mut body = b"WAVE".to_vec();
+        for (id, chunk) in [(b"fmt ", fmt), (b"LIST", b"INFO".as_ref()), (b"data", data)].iter() {
+            body.extend_from_slice(*id);
+            body.extend_from_slice(&(chunk.len() as u32).to_le_bytes());
+            body.extend_from_slice(chunk);
+        }
+        let mut bytes = b"RIFF".to_vec();
+        bytes.extend_from_slice(&(body.len() as u32).to_le_bytes());
+        bytes.extend(body);
+        bytes
+    }
+
+    fn wav_fmt(format_tag: u16, channel_count: u16, bits_per_sample: u16) -> Vec<u8> {
+        let block_align = channel_count * bits_per_sample.div_ceil(8);
+        let mut fmt = Vec::new();
+        fmt.extend_from_slice(&format_tag.to_le_bytes());
+        fmt.extend_from_slice(&channel_count.to_le_bytes());
+        fmt.extend_from_slice(&44100u32.to_le_bytes());
+        fmt.extend_from_slice(&(44100 * block_align as u32).to_le_bytes());
+        fmt.extend_from_slice(&block_align.to_le_bytes());
+        fmt.extend_from_slice(&bits_per_sample.to_le_bytes());
+        fmt
+    }
+
+    fn wav_fmt_extensible(sub_format: u16, channel_count: u16, bits_per_sample: u16) -> Vec<u8> {
+        let mut fmt = wav_fmt(WAV_FORMAT_EXTENSIBLE, channel_count, bits_per_sample);
+        fmt.extend_from_slice(&22u16.to_le_bytes());
+        fmt.extend_from_slice(&bits_per_sample.to_le_bytes());
+        // front left and right speakers
+        fmt.extend_from_slice(&3u32.to_le_bytes());
+        fmt.extend_from_slice(&sub_format.to_le_bytes());
+        fmt.extend_from_slice(&WAV_SUBFORMAT_SUFFIX);
+        fmt
+    }
+
+    #[test]
+    fn test_read_wav() -> anyhow::Result<()> {
+        let read = |fmt: Vec<u8>, data: &[u8]| {
+            let mut reader = Cursor::new(wav(&fmt, data));
+            assert_eq!(detect_format(&mut reader)?, SampleFormat::Wav);
+            let (header, bit_depth) = read_wav(&mut reader)?;
+            Ok::<_, anyhow::Error>((header, convert_bit_depth(bit_depth)?))
+        };
+
+        let (header, data) = read(
+            wav_fmt(WAV_FORMAT_PCM, 2, 16),
+            &[0xff, 0x7f, 0x00, 0x80, 0x01, 0x00, 0xff, 0xff],
+        )?;
+        assert_eq!(
+            header,
+            SampleHeader {
+                format: SampleFormat::Wav,
+                channel_count: 2,
+                sampling_rate: 44100,
+                bits_per_sample: 16,
+            }
+        );
+        assert_eq!(data, vec![i16::MAX, i16::MIN, 1, -1]);
+        let bytes = wav(&wav_fmt(WAV_FORMAT_PCM, 2, 16), &[0; 12]);
+        assert_eq!(read_wav_info(&mut Cursor::new(bytes))?, (header, 3));
+
+        // 24 bit stereo extensible
+        let (header, data) = read(
+            wav_fmt_extensible(WAV_FORMAT_PCM, 2, 24),
+            &[
+                0xff, 0xff, 0x7f, 0x00, 0x00, 0x80, 0x00, 0x01, 0x00, 0x00, 0xff, 0xff,
+            ],
+        )?;
+        assert_eq!((header.channel_count, header.bits_per_sample), (2, 24));
+        assert_eq!(data, vec![i16::MAX, i16::MIN, 1, -1]);
+
+        // 32 bit integer, plain and extensible
+        let int32 = [0xff, 0xff, 0xff, 0x7f, 0x00, 0x00, 0x00, 0x80];
+        let (_, data) = read(wav_fmt(WAV_FORMAT_PCM, 1, 32), &int32)?;
+        assert_eq!(data, vec![i16::MAX, i16::MIN]);
+        let (_, data) = read(wav_fmt_extensible(WAV_FORMAT_PCM, 1, 32), &int32)?;
+        assert_eq!(data, vec![i16::MAX, i16::MIN]);
+
+        let float = [-1.0f32, 0.5]
+            .iter()
+            .flat_map(|f| f.to_le_bytes().to_vec())
+            .collect::<Vec<_>>();
+        let (_, data) = read(wav_fmt_extensible(WAV_FORMAT_IEEE_FLOAT, 1, 32), &float)?;
+        assert_eq!(data, vec![-i16::MAX, 16384]);
+
+        let (_, data) = read(wav_fmt(WAV_FORMAT_PCM, 1, 8), &[0, 128, 255])?;
+        assert_eq!(data, vec![i16::MIN, 0, 127 << 8]);
+
+        // compressed formats are rejected
+        assert!(read(wav_fmt(0x0002, 1, 4), &[0, 0]).is_err());
+        assert!(read(wav_fmt_extensible(0x0002, 1, 16), &[0, 0]).is_err());
+        assert!(read(wav_fmt(WAV_FORMAT_PCM, 1, 16)[..14].to_vec(), &[0, 0]).is_err());
+
+        // 0 Hz sampling rate
+        let mut fmt = wav_fmt(WAV_FORMAT_PCM, 1, 16);
+        fmt[4..8].fill(0);
+        assert!(read(fmt, &[0, 0]).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_detect_format() {
+        let mut wav = Cursor::new(b"RIFF\0\0\0\0WAVEfmt ".to_vec());
+        assert_eq!(detect_format(&mut wav).unwrap(), SampleFormat::Wav);
+        let mut flac = Cursor::new(b"fLaC\0\0\0\x22\0\0\0\0".to_vec());
+        assert_eq!(detect_format(&mut flac).unwrap(), SampleFormat::Flac);
+        let mut unknown = Cursor::new(b"OggS\0\0\0\0\0\0\0\0".to_vec());
+        assert!(detect_format(&mut unknown).is_err());
+    }
+
+    #[test]
+    fn test_to_mono() -> anyhow::Result<()> {
+        let stereo = vec![100, -100, 200, 0, i16::MAX, i16::MAX];
+
+        assert_eq!(
+            to_mono(stereo.clone(), 2, ChannelsDef::Mix)?,
+            vec![0, 100, i16::MAX]
+        );
+        assert_eq!(
+            to_mono(stereo.clone(), 2, ChannelsDef::Left)?,
+            vec![100, 200, i16::MAX]
+        );
+        assert_eq!(
+            to_mono(stereo.clone(), 2, ChannelsDef::Right)?,
+            vec![-100, 0, i16::MAX]
+        );
+        assert_eq!(
+            to_mono(stereo.clone(), 2, ChannelsDef::Mid)?,
+            vec![0, 100, i16::MAX]
+        );
+        assert_eq!(to_mono(stereo, 2, ChannelsDef::Side)?, vec![100, 100, 0]);
+
+        let quad = vec![4, 8, 12, 16];
+        assert_eq!(to_mono(quad, 4, ChannelsDef::Mix)?, vec![10]);
+
+        let mono = vec![1, 2, 3];
+        assert_eq!(to_mono(mono.clone(), 1, ChannelsDef::Left)?, mono);
+        assert!(to_mono(mono, 1, ChannelsDef::Side).is_err());
+        Ok(())
+    }
+
+    fn sine(frequency: f64, rate: u32, len: usize) -> Vec<i16> {
+        (0..len)
+            .map(|n| {
+                let t = n as f64 / rate as f64;
+                (10000.0 * (2.0 * std::f64::consts::PI * frequency * t).sin()) as i16
+            })
+            .collect()
+    }
+
+    fn rms(data: &[i16]) -> f64 {
+        (data.iter().map(|&s| (s as f64).powi(2)).sum::<f64>() / data.len() as f64).sqrt()
+    }
+
+    #[test]
+    fn test_trim_region() -> anyhow::Result<()> {
+        let data: Vec<i16> = (0..1000).collect();
+        assert_eq!(trim_region(data.clone(), 1000, None, None)?, data);
+        assert_eq!(
+            trim_region(
+                data.clone(),
+                1000,
+                Some(OffsetDef::Frames(10)),
+                Some(OffsetDef::Milliseconds(12.0))
+            )?,
+            vec![10, 11]
+        );
+        assert_eq!(
+            trim_region(data.clone(), 1000, Some(OffsetDef::Seconds(0.998)), None)?,
+            vec![998, 999]
+        );
+        assert!(trim_region(data.clone(), 1000, None, Some(OffsetDef::Frames(1001))).is_err());
+        assert!(trim_region(
+            data.clone(),
+            1000,
+            Some(OffsetDef::Frames(20)),
+            Some(OffsetDef::Frames(20))
+        )
+        .is_err());
+        assert!(trim_region(data, 1000, None, Some(OffsetDef::Frames(0))).is_err());
+        assert!(trim_region(Vec::new(), 1000, None, None).is_err());
+        assert!(trim_region(Vec::new(), 1000, Some(OffsetDef::Frames(0)), None).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_slice() -> anyhow::Result<()> {
+        let data: Vec<i16> = (0..10).collect();
+        assert_eq!(
+            slice_equal(&data, 3)?,
+            vec![vec![0, 1, 2], vec![3, 4, 5], vec![6, 7, 8, 9]]
+        );
+        assert!(slice_equal(&data, 11).is_err());
+
+        let markers = [OffsetDef::Frames(2), OffsetDef::Milliseconds(500.0)];
+        assert_eq!(
+            slice_at(&data, 10, &markers)?,
+            vec![vec![0, 1], vec![2, 3, 4], vec![5, 6, 7, 8, 9]]
+        );
+        let unordered = [OffsetDef::Frames(5), OffsetDef::Frames(2)];
+        assert!(slice_at(&data, 10, &unordered).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_trim_silence() -> anyhow::Result<()> {
+        let data = vec![0, 3, -2, 500, 0, -800, 10, 0];
+        // -40 dBFS is an amplitude of ~328
+        assert_eq!(trim_silence(data.clone(), -40.0)?, vec![500, 0, -800]);
+        assert_eq!(
+            trim_silence(data.clone(), -90.0)?,
+            vec![3, -2, 500, 0, -800, 10]
+        );
+        assert!(trim_silence(data.clone(), -6.0).is_err());
+        assert!(trim_silence(data.clone(), 1.0).is_err());
+
+        assert!(is_silent(&data, -6.0));
+        assert!(!is_silent(&data, -40.0));
+        assert!(is_silent(&[], -40.0));
+        Ok(())
+    }
+
+    #[test]
+    fn test_normalize() -> anyhow::Result<()> {
+        let data = vec![0, 1000, -2000];
+        assert_eq!(normalize(data.clone(), 0.0)?, vec![0, 16384, -32767]);
+        // -6.0206 dBFS is half of full scale
+        assert_eq!(normalize(data.clone(), -6.0206)?, vec![0, 8192, -16383]);
+        assert_eq!(normalize(vec![0, 0], -1.0)?, vec![0, 0]);
+        assert!(normalize(data, 3.0).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_resample() -> anyhow::Result<()> {
+        let passband = sine(1000.0, 96000, 9600);
+        let resampled = resample(&passband, 96000, 31250)?;
+        assert_eq!(resampled.len(), 3125);
+        // skip the edges where the filter runs out of input
+        let middle = &resampled[100..3025];
+        assert!((rms(middle) - rms(&passband)).abs() < rms(&passband) * 0.02);
+
+        // 20kHz is above the nyquist frequency of 31.25kHz and should be filtered out
+        let stopband = sine(20000.0, 96000, 9600);
+        let resampled = resample(&stopband, 96000, 31250)?;
+        assert!(rms(&resampled[100..3025]) < rms(&stopband) * 0.01);
+
+        let upsampled = resample(&sine(1000.0, 22050, 2205), 22050, 44100)?;
+        assert_eq!(upsampled.len(), 4410);
+
+        assert_eq!(resample(&passband, 96000, 96000)?, passband);
+        assert!(resample(&passband, 96000, 0).is_err());
+        Ok(())
+    }
+}
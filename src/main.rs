@@ -1,105 +1,552 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
 use std::ffi::OsStr;
-use std::fs::{read, read_to_string, File};
-use std::io::BufWriter;
+use std::fs::{read, read_to_string, write, File};
+use std::io::{stdin, stdout, BufWriter, Read, Write};
 use std::path::{Path, PathBuf};
 
 use anyhow::Context;
 use clap::{App, AppSettings, Arg, ArgMatches, SubCommand};
 use korg_syro::SyroStream;
-use log::{debug, info};
+use log::{debug, info, warn};
 use simple_logger::SimpleLogger;
-use wav;
 
+mod audio;
+mod memory;
 mod parse;
+mod validate;
+use audio::*;
+use memory::*;
 use parse::*;
+use validate::*;
 
-fn get_data(file_name: &str) -> anyhow::Result<VolcaSample> {
-    let data_string =
-        read_to_string(file_name).with_context(|| format!("Cannot open file '{}'", file_name))?;
-    let extension = Path::new(file_name)
-        .extension()
-        .and_then(OsStr::to_str)
-        .expect("No file extension, cannot infer format");
-    let data = match extension {
-        "ron" => ron::de::from_str::<VolcaSample>(data_string.as_str())
-            .with_context(|| format!("Cannot deserialize ron data in file '{}'", file_name))?,
-        "json" => serde_json::from_str::<VolcaSample>(data_string.as_str())
-            .with_context(|| format!("Cannot deserialize json data in file '{}'", file_name))?,
-        "yaml" => serde_yaml::from_str::<VolcaSample>(data_string.as_str())
-            .with_context(|| format!("Cannot deserialize yaml data in file '{}'", file_name))?,
-        _ => return Err(anyhow::anyhow!("Unkonwn file format")),
+// Input file name that reads the configuration from stdin
+const STDIN: &str = "-";
+
+// Reads and validates a single configuration file, returning it with its source text
+fn read_config(
+    file_name: &str,
+    format: Option<ConfigFormat>,
+) -> anyhow::Result<(VolcaSample, String)> {
+    let data_string = if file_name == STDIN {
+        let mut data_string = String::new();
+        stdin()
+            .read_to_string(&mut data_string)
+            .context("Cannot read from stdin")?;
+        data_string
+    } else {
+        read_to_string(file_name).with_context(|| format!("Cannot open file '{}'", file_name))?
     };
+    let format = format
+        .or_else(|| {
+            Path::new(file_name)
+                .extension()
+                .and_then(OsStr::to_str)
+                .and_then(ConfigFormat::from_name)
+        })
+        .or_else(|| ConfigFormat::detect(data_string.as_str()))
+        .ok_or_else(|| {
+            anyhow::anyhow!(
+                "Cannot infer format of file '{}', use --format to set it",
+                file_name
+            )
+        })?;
+    let data = format.deserialize(data_string.as_str()).with_context(|| {
+        format!(
+            "Cannot deserialize {} data in file '{}'",
+            format.name(),
+            file_name
+        )
+    })?;
+    check_errors(validate(&data, data_string.as_str()), file_name)?;
     info!("Loaded data from file '{}'", file_name);
+    Ok((data, data_string))
+}
+
+fn check_errors(errors: Vec<ValidationError>, file_name: &str) -> anyhow::Result<()> {
+    if !errors.is_empty() {
+        return Err(ValidationErrors {
+            file_name: String::from(file_name),
+            errors,
+        }
+        .into());
+    }
+    Ok(())
+}
+
+fn get_data(file_name: &str, format: Option<ConfigFormat>) -> anyhow::Result<VolcaSample> {
+    read_data(file_name, format, &mut Err, &mut stdout())
+}
+
+/// Reads a configuration file with its includes, sample_dirs, part templates and sample names
+/// applied. Problems found after the file itself was read are passed to `on_error`, which either
+/// returns them to stop or records them to go on with what could be applied. The slots the
+/// sample_dirs files were put in are printed to `out`.
+fn read_data(
+    file_name: &str,
+    format: Option<ConfigFormat>,
+    on_error: &mut dyn FnMut(anyhow::Error) -> anyhow::Result<()>,
+    out: &mut dyn Write,
+) -> anyhow::Result<VolcaSample> {
+    let input_dir = Path::new(file_name).parent().unwrap_or(Path::new("."));
+    let (mut data, mut source) = read_config(file_name, format)?;
+    if data.include.is_some() {
+        let mut included_from = Vec::new();
+        if file_name != STDIN {
+            included_from.push(Path::new(file_name).canonicalize()?);
+        }
+        let mut merged = BTreeSet::new();
+        if let Err(e) = include_configs(
+            &mut data,
+            input_dir,
+            Path::new(""),
+            &mut included_from,
+            &mut merged,
+        ) {
+            on_error(e)?;
+        }
+        // slices from different files can still overlap, and the merged configuration has no
+        // source to locate errors in
+        source.clear();
+        if let Err(e) = check_errors(validate(&data, ""), file_name) {
+            on_error(e)?;
+        }
+    }
+    if let Err(e) = expand_sample_dirs(&mut data, input_dir, out) {
+        on_error(e)?;
+    }
+    if let Err(e) = check_errors(apply_part_templates(&mut data, &source), file_name) {
+        on_error(e)?;
+    }
+    if let Err(e) = check_errors(resolve_sample_names(&mut data, &source), file_name) {
+        on_error(e)?;
+    }
     Ok(data)
 }
 
-fn read_sample(file_path: &Path) -> anyhow::Result<(wav::Header, Vec<i16>)> {
-    let mut file = File::open(&file_path).with_context(|| "Cannot open input file")?;
-    let (header, bit_depth) = wav::read(&mut file).with_context(|| "Cannot read input file")?;
+/// Merges the configurations included by `data` into it, `dir` is the directory of the
+/// including file relative to `input_dir`. Files in `merged` were already included through
+/// another file and are skipped.
+fn include_configs(
+    data: &mut VolcaSample,
+    input_dir: &Path,
+    dir: &Path,
+    included_from: &mut Vec<PathBuf>,
+    merged: &mut BTreeSet<PathBuf>,
+) -> anyhow::Result<()> {
+    for include in data.include.take().unwrap_or_default() {
+        let base = glob::Pattern::escape(&input_dir.join(dir).to_string_lossy());
+        let pattern = Path::new(&base).join(&include);
+        let mut paths = glob::glob(&pattern.to_string_lossy())
+            .with_context(|| format!("Invalid include pattern '{}'", include))?
+            .collect::<Result<Vec<_>, _>>()?;
+        if paths.is_empty() {
+            return Err(anyhow::anyhow!(
+                "No configuration files match include '{}'",
+                include
+            ));
+        }
+        paths.sort();
 
-    //TODO conversions
-    let data = match bit_depth {
-        wav::BitDepth::Eight(_) => Err(anyhow::anyhow!("8 bit stream not supported yet")),
-        wav::BitDepth::Sixteen(d) => Ok(d),
-        wav::BitDepth::TwentyFour(_) => Err(anyhow::anyhow!("24 bit stream not supported yet")),
-        wav::BitDepth::Empty => Err(anyhow::anyhow!("empty?? bit stream not supported yet")),
-    }?;
-    Ok((header, data))
+        for path in paths {
+            let file_name = path.to_string_lossy();
+            let canonical = path.canonicalize()?;
+            if included_from.contains(&canonical) {
+                return Err(anyhow::anyhow!("File '{}' includes itself", file_name));
+            }
+            if merged.contains(&canonical) {
+                debug!("Skipping '{}', it is already included", file_name);
+                continue;
+            }
+            let (mut included, _) = read_config(&file_name, None)?;
+
+            // sample paths of the included file are relative to it, make them relative to
+            // input_dir like the ones of the including file
+            let relative = path.strip_prefix(input_dir).unwrap_or(&path);
+            let included_dir = relative.parent().unwrap_or(Path::new(""));
+            for sample_action in included.samples.iter_mut().flat_map(|s| s.values_mut()) {
+                match sample_action {
+                    SampleAction::Sample(SampleDef { file, .. })
+                    | SampleAction::Slices(SlicesDef { file, .. }) => {
+                        *file = resolve_sample_path(included_dir, file)
+                            .to_string_lossy()
+                            .into_owned();
+                    }
+                    SampleAction::Erase => {}
+                }
+            }
+            let escaped_dir = glob::Pattern::escape(&included_dir.to_string_lossy());
+            for sample_dir in included.sample_dirs.iter_mut().flatten() {
+                sample_dir.files = resolve_sample_path(Path::new(&escaped_dir), &sample_dir.files)
+                    .to_string_lossy()
+                    .into_owned();
+            }
+
+            included_from.push(canonical.clone());
+            include_configs(
+                &mut included,
+                input_dir,
+                included_dir,
+                included_from,
+                merged,
+            )?;
+            included_from.pop();
+            data.merge(included)
+                .with_context(|| format!("Cannot include file '{}'", file_name))?;
+            merged.insert(canonical);
+        }
+    }
+    Ok(())
+}
+
+/// Adds the files matched by each sample_dirs entry to the samples, in consecutive slots from its
+/// slot or otherwise in the first free slots, and prints where each file went to `out`
+fn expand_sample_dirs(
+    data: &mut VolcaSample,
+    input_dir: &Path,
+    out: &mut dyn Write,
+) -> anyhow::Result<()> {
+    let sample_dirs = match data.sample_dirs.take() {
+        Some(sample_dirs) => sample_dirs,
+        None => return Ok(()),
+    };
+    let samples = data.samples.get_or_insert_with(HashMap::new);
+    let mut occupied = BTreeSet::new();
+    for (index, sample_action) in samples.iter() {
+        let slot_count = match sample_action {
+            SampleAction::Slices(slices) => slices.slot_count()?,
+            _ => 1,
+        };
+        occupied.extend(*index..index + slot_count);
+    }
+
+    let escaped_dir = glob::Pattern::escape(&input_dir.to_string_lossy());
+    for (i, sample_dir) in sample_dirs.into_iter().enumerate() {
+        let pattern = resolve_sample_path(Path::new(&escaped_dir), &sample_dir.files);
+        let mut paths = glob::glob(&pattern.to_string_lossy())
+            .with_context(|| format!("Invalid sample_dirs pattern '{}'", sample_dir.files))?
+            .collect::<Result<Vec<_>, _>>()?;
+        paths.retain(|path| path.is_file() && is_sample_file(path));
+        if paths.is_empty() {
+            return Err(anyhow::anyhow!(
+                "No sample files match sample_dirs pattern '{}'",
+                sample_dir.files
+            ));
+        }
+        paths.sort();
+
+        let mut slot = sample_dir.slot.unwrap_or(0);
+        for path in paths {
+            if sample_dir.slot.is_none() {
+                while occupied.contains(&slot) {
+                    slot += 1;
+                }
+            }
+            let file = path.strip_prefix(input_dir).unwrap_or(&path);
+            if slot > 99 {
+                return Err(anyhow::anyhow!(
+                    "No free sample slot left for '{}' of sample_dirs[{}]",
+                    file.to_string_lossy(),
+                    i
+                ));
+            }
+            if !occupied.insert(slot) {
+                return Err(anyhow::anyhow!(
+                    "Sample slot {} for '{}' of sample_dirs[{}] is already used",
+                    slot,
+                    file.to_string_lossy(),
+                    i
+                ));
+            }
+            writeln!(out, "samples.{}: {}", slot, file.to_string_lossy())?;
+            samples.insert(
+                slot,
+                SampleAction::Sample(SampleDef {
+                    file: file.to_string_lossy().into_owned(),
+                    name: None,
+                    compression: sample_dir.compression,
+                    channels: sample_dir.channels,
+                    resample: sample_dir.resample,
+                    start: None,
+                    end: None,
+                    trim_silence: sample_dir.trim_silence,
+                    normalize: sample_dir.normalize,
+                }),
+            );
+            slot += 1;
+        }
+    }
+    Ok(())
 }
 
 fn get_output_file(arg_matches: &ArgMatches, input_file: &str) -> String {
     let mut dir = std::env::current_dir().unwrap_or(PathBuf::from("."));
     arg_matches
         .value_of("output")
-        .map(|s| String::from(s))
+        .map(String::from)
         .or_else(|| {
+            let input_file = match input_file {
+                STDIN => "stdin",
+                _ => input_file,
+            };
             Path::new(input_file)
                 .file_stem()
                 .and_then(|os_s| os_s.to_str())
                 .and_then(|s| {
                     dir.push(format!("{}.wav", s));
-                    dir.to_str().map(|s| String::from(s))
+                    dir.to_str().map(String::from)
                 })
         })
         .unwrap()
 }
 
-fn load(input_file: &str, output_file: &str) -> anyhow::Result<()> {
-    let input_dir = Path::new(input_file).parent().unwrap_or(Path::new("."));
-    let volca_sample = get_data(input_file)?;
+fn get_format(arg_matches: &ArgMatches) -> Option<ConfigFormat> {
+    arg_matches
+        .value_of("format")
+        .and_then(ConfigFormat::from_name)
+}
 
-    let mut syro_stream = SyroStream::default();
+fn resolve_sample_path(input_dir: &Path, file: &str) -> PathBuf {
+    let expanded = shellexpand::tilde(file);
+    let path = Path::new(expanded.as_ref());
+    match path.is_absolute() {
+        true => path.into(),
+        false => input_dir.join(path),
+    }
+}
 
-    debug!("Parsing samples...");
-    if let Some(samples) = volca_sample.samples {
-        for (index, sample_action) in samples {
-            match sample_action {
-                SampleAction::Sample(sample) => {
-                    let file_path = {
-                        let expanded = shellexpand::tilde(sample.file.as_str());
-                        let path = Path::new(expanded.as_ref());
-                        match path.is_absolute() {
-                            true => path.into(),
-                            false => input_dir.join(path).into_boxed_path(),
-                        }
-                    };
-                    let (header, data) = read_sample(&file_path)?;
-                    let compression = sample.compression.or(volca_sample.default_compression);
-
-                    debug!(
-                        "Sample {} '{}', duration = {}s, compression = {:?}, wav: {:?}",
-                        index,
+fn read_mono_sample(
+    file_path: &Path,
+    channels: Option<ChannelsDef>,
+) -> anyhow::Result<(SampleHeader, Vec<i16>)> {
+    let (header, data) = read_sample(file_path)
+        .with_context(|| format!("Cannot read sample '{}'", file_path.to_string_lossy()))?;
+    let data = to_mono(
+        data,
+        header.channel_count,
+        channels.unwrap_or(ChannelsDef::Mix),
+    )
+    .with_context(|| format!("Cannot read channels of '{}'", file_path.to_string_lossy()))?;
+    Ok((header, data))
+}
+
+// Processing applied to a sample before it's added, with the configured defaults filled in
+struct SampleOptions {
+    trim_silence: Option<f64>,
+    resample: Option<u32>,
+    normalize: Option<f64>,
+    compression: Option<u32>,
+    // Whether compression was set on the sample itself, which fit_memory never changes
+    fixed_compression: bool,
+}
+
+// A sample that has been read and processed, ready to be added to the stream
+struct PreparedSample {
+    file_path: PathBuf,
+    data: Vec<i16>,
+    sampling_rate: u32,
+    compression: Option<u32>,
+    fixed_compression: bool,
+}
+
+enum SlotContent {
+    Sample(PreparedSample),
+    Erase,
+}
+
+fn process_sample(
+    index: u32,
+    file_path: &Path,
+    header: &SampleHeader,
+    data: Vec<i16>,
+    options: &SampleOptions,
+) -> anyhow::Result<PreparedSample> {
+    let data = match options.trim_silence {
+        Some(threshold) => trim_silence(data, threshold)
+            .with_context(|| format!("Cannot trim silence of '{}'", file_path.to_string_lossy()))?,
+        None => data,
+    };
+    let (data, sampling_rate) = match options.resample {
+        Some(rate) => (
+            resample(&data, header.sampling_rate, rate)
+                .with_context(|| format!("Cannot resample '{}'", file_path.to_string_lossy()))?,
+            rate,
+        ),
+        None => (data, header.sampling_rate),
+    };
+    let data = match options.normalize {
+        Some(peak) => normalize(data, peak)
+            .with_context(|| format!("Cannot normalize '{}'", file_path.to_string_lossy()))?,
+        None => data,
+    };
+
+    debug!(
+        "Sample {} '{}', duration = {}s, sampling rate = {}, compression = {:?}, input: {:?}",
+        index,
+        file_path.to_string_lossy(),
+        data.len() as f32 / sampling_rate as f32,
+        sampling_rate,
+        options.compression,
+        header
+    );
+    Ok(PreparedSample {
+        file_path: file_path.into(),
+        data,
+        sampling_rate,
+        compression: options.compression,
+        fixed_compression: options.fixed_compression,
+    })
+}
+
+/// Reads and processes all sample files of the configuration, by sample slot
+fn prepare_samples(
+    volca_sample: &VolcaSample,
+    input_dir: &Path,
+) -> anyhow::Result<BTreeMap<u32, SlotContent>> {
+    let mut slots = BTreeMap::new();
+    let samples = match &volca_sample.samples {
+        Some(samples) => samples,
+        None => return Ok(slots),
+    };
+    for (index, sample_action) in samples {
+        prepare_sample_action(volca_sample, input_dir, *index, sample_action, &mut slots)?;
+    }
+
+    if volca_sample.fit_memory == Some(true) {
+        fit_sample_memory(volca_sample, &mut slots)?;
+    }
+    Ok(slots)
+}
+
+// Reads and processes the sample file of a sample action into the slots it takes up
+fn prepare_sample_action(
+    volca_sample: &VolcaSample,
+    input_dir: &Path,
+    index: u32,
+    sample_action: &SampleAction,
+    slots: &mut BTreeMap<u32, SlotContent>,
+) -> anyhow::Result<()> {
+    match sample_action {
+        SampleAction::Sample(sample) => {
+            let file_path = resolve_sample_path(input_dir, &sample.file);
+            let (header, data) = read_mono_sample(&file_path, sample.channels)?;
+            let data = trim_region(data, header.sampling_rate, sample.start, sample.end)
+                .with_context(|| format!("Cannot trim '{}'", file_path.to_string_lossy()))?;
+            let options = SampleOptions {
+                trim_silence: sample.trim_silence.or(volca_sample.default_trim_silence),
+                resample: sample.resample.or(volca_sample.default_resample),
+                normalize: sample.normalize.or(volca_sample.default_normalize),
+                compression: sample.compression.or(volca_sample.default_compression),
+                fixed_compression: sample.compression.is_some(),
+            };
+            let prepared = process_sample(index, &file_path, &header, data, &options)?;
+            slots.insert(index, SlotContent::Sample(prepared));
+        }
+        SampleAction::Slices(slices) => {
+            let file_path = resolve_sample_path(input_dir, &slices.file);
+            let (header, data) = read_mono_sample(&file_path, slices.channels)?;
+            let sliced = match &slices.markers {
+                Some(markers) => slice_at(&data, header.sampling_rate, markers),
+                None => slice_equal(&data, slices.slot_count()?),
+            }
+            .with_context(|| format!("Cannot slice '{}'", file_path.to_string_lossy()))?;
+            let options = SampleOptions {
+                trim_silence: slices.trim_silence.or(volca_sample.default_trim_silence),
+                resample: slices.resample.or(volca_sample.default_resample),
+                normalize: slices.normalize.or(volca_sample.default_normalize),
+                compression: slices.compression.or(volca_sample.default_compression),
+                fixed_compression: slices.compression.is_some(),
+            };
+            for (i, (slot, slice)) in (index..).zip(sliced).enumerate() {
+                // a silent slice is kept as it is instead of failing the whole file
+                let silent = options
+                    .trim_silence
+                    .is_some_and(|threshold| is_silent(&slice, threshold));
+                if silent {
+                    warn!(
+                        "Slice {} of '{}' in slot {} is silent, not trimming it",
+                        i,
                         file_path.to_string_lossy(),
-                        data.len() as f32 / header.sampling_rate as f32,
-                        compression,
-                        header
+                        slot
                     );
-                    syro_stream.add_sample(index, data, header.sampling_rate, compression)?;
-                }
-                SampleAction::Erase => {
-                    debug!("Erase {}", index);
-                    syro_stream.erase_sample(index)?;
                 }
+                let slice_options = SampleOptions {
+                    trim_silence: options.trim_silence.filter(|_| !silent),
+                    ..options
+                };
+                let prepared = process_sample(slot, &file_path, &header, slice, &slice_options)?;
+                slots.insert(slot, SlotContent::Sample(prepared));
+            }
+        }
+        SampleAction::Erase => {
+            debug!("Erase {}", index);
+            slots.insert(index, SlotContent::Erase);
+        }
+    }
+    Ok(())
+}
+
+/// Lowers the compression bit depth of samples until they fit in sample memory,
+/// samples referenced by fewer pattern parts are lowered first
+fn fit_sample_memory(
+    volca_sample: &VolcaSample,
+    slots: &mut BTreeMap<u32, SlotContent>,
+) -> anyhow::Result<()> {
+    let references = volca_sample.sample_references();
+    let mut fixed_size = 0;
+    let mut candidates = Vec::new();
+    let mut candidate_slots = Vec::new();
+    for (index, slot) in slots.iter() {
+        if let SlotContent::Sample(sample) = slot {
+            let frames = device_frames(sample.data.len(), sample.sampling_rate)?;
+            if sample.fixed_compression {
+                fixed_size += estimated_size(frames, sample.compression);
+            } else {
+                candidates.push(CompressionCandidate {
+                    device_frames: frames,
+                    compression: sample.compression.unwrap_or(16),
+                    references: references.get(index).copied().unwrap_or(0),
+                });
+                candidate_slots.push(*index);
+            }
+        }
+    }
+
+    let fitted = fit_compression(fixed_size, &candidates)?;
+    for (index, compression) in candidate_slots.into_iter().zip(fitted) {
+        if let Some(SlotContent::Sample(sample)) = slots.get_mut(&index) {
+            if compression < sample.compression.unwrap_or(16) {
+                info!(
+                    "Sample {} '{}' compressed to {} bits to fit sample memory",
+                    index,
+                    sample.file_path.to_string_lossy(),
+                    compression
+                );
+                sample.compression = Some(compression);
+            }
+        }
+    }
+    Ok(())
+}
+
+fn load(input_file: &str, format: Option<ConfigFormat>, output_file: &str) -> anyhow::Result<()> {
+    let input_dir = Path::new(input_file).parent().unwrap_or(Path::new("."));
+    let volca_sample = get_data(input_file, format)?;
+
+    let mut syro_stream = SyroStream::default();
+
+    debug!("Parsing samples...");
+    for (index, slot) in prepare_samples(&volca_sample, input_dir)? {
+        match slot {
+            SlotContent::Sample(sample) => {
+                syro_stream.add_sample(
+                    index,
+                    sample.data,
+                    sample.sampling_rate,
+                    sample.compression,
+                )?;
+            }
+            SlotContent::Erase => {
+                syro_stream.erase_sample(index)?;
             }
         }
     }
@@ -123,7 +570,7 @@ fn load(input_file: &str, output_file: &str) -> anyhow::Result<()> {
         header
     );
 
-    let output = File::create(&output_file)?;
+    let output = File::create(output_file)?;
 
     wav::write(
         header,
@@ -135,6 +582,347 @@ fn load(input_file: &str, output_file: &str) -> anyhow::Result<()> {
     Ok(())
 }
 
+fn plan(input_file: &str, format: Option<ConfigFormat>) -> anyhow::Result<()> {
+    let input_dir = Path::new(input_file).parent().unwrap_or(Path::new("."));
+    let volca_sample = get_data(input_file, format)?;
+    let slots = prepare_samples(&volca_sample, input_dir)?;
+
+    println!(
+        "{:>4}  {:>9}  {:>9}  {:>11}  {:>9}  file",
+        "slot", "duration", "frames", "compression", "bytes"
+    );
+    let mut total = 0;
+    for (index, slot) in slots {
+        match slot {
+            SlotContent::Sample(sample) => {
+                let frames = device_frames(sample.data.len(), sample.sampling_rate)?;
+                let bytes = estimated_size(frames, sample.compression);
+                total += bytes;
+                println!(
+                    "{:>4}  {:>8.3}s  {:>9}  {:>11}  {:>9}  {}",
+                    index,
+                    sample.data.len() as f64 / sample.sampling_rate as f64,
+                    frames,
+                    sample.compression.unwrap_or(16),
+                    bytes,
+                    sample.file_path.to_string_lossy()
+                );
+            }
+            SlotContent::Erase => println!("{:>4}  erase", index),
+        }
+    }
+    println!(
+        "total {} of {} bytes ({:.1}%)",
+        total,
+        DEVICE_MEMORY_SIZE,
+        total as f64 * 100.0 / DEVICE_MEMORY_SIZE as f64
+    );
+
+    if total > DEVICE_MEMORY_SIZE {
+        return Err(anyhow::anyhow!(
+            "Samples need an estimated {} bytes, which exceeds the {} bytes of sample memory",
+            total,
+            DEVICE_MEMORY_SIZE
+        ));
+    }
+    Ok(())
+}
+
+/// Runs every check `load` does on a configuration file without generating any output,
+/// returning all problems found, the slots of sample_dirs files are printed to `out`
+fn check(input_file: &str, format: Option<ConfigFormat>, out: &mut dyn Write) -> Vec<String> {
+    let input_dir = Path::new(input_file).parent().unwrap_or(Path::new("."));
+    let mut problems: Vec<String> = Vec::new();
+    // patterns are only parsed when the configuration is valid, otherwise they fail on the
+    // problems that were already found
+    let mut valid = true;
+    let mut record = |e: anyhow::Error| {
+        let messages = match e.downcast::<ValidationErrors>() {
+            Ok(invalid) => {
+                valid = false;
+                invalid
+                    .errors
+                    .iter()
+                    .map(|error| match invalid.file_name.as_str() {
+                        file_name if file_name == input_file => error.to_string(),
+                        file_name => format!("{}: {}", file_name, error),
+                    })
+                    .collect()
+            }
+            Err(e) => vec![format!("{:#}", e)],
+        };
+        // a merged configuration is validated both before and after its part templates are applied
+        for message in messages {
+            if !problems.contains(&message) {
+                problems.push(message);
+            }
+        }
+        Ok(())
+    };
+    let volca_sample = match read_data(input_file, format, &mut record, out) {
+        Ok(volca_sample) => volca_sample,
+        Err(e) => {
+            record(e).ok();
+            return problems;
+        }
+    };
+    // samples are only checked against the memory size when they could all be prepared
+    let mut prepared = true;
+
+    let mut slots = BTreeMap::new();
+    if let Some(samples) = &volca_sample.samples {
+        let mut samples: Vec<_> = samples.iter().collect();
+        samples.sort_by_key(|(index, _)| **index);
+        for (index, sample_action) in samples {
+            if let Err(e) =
+                prepare_sample_action(&volca_sample, input_dir, *index, sample_action, &mut slots)
+            {
+                problems.push(format!("samples.{}: {:#}", index, e));
+                prepared = false;
+            }
+        }
+    }
+    if prepared {
+        if volca_sample.fit_memory == Some(true) {
+            if let Err(e) = fit_sample_memory(&volca_sample, &mut slots) {
+                problems.push(format!("{:#}", e));
+            }
+        } else {
+            let total = slots
+                .values()
+                .map(|slot| match slot {
+                    SlotContent::Sample(sample) => Ok(estimated_size(
+                        device_frames(sample.data.len(), sample.sampling_rate)?,
+                        sample.compression,
+                    )),
+                    SlotContent::Erase => Ok(0),
+                })
+                .sum::<anyhow::Result<u64>>();
+            match total {
+                Ok(total) if total > DEVICE_MEMORY_SIZE => problems.push(format!(
+                    "Samples need an estimated {} bytes, which exceeds the {} bytes of sample memory",
+                    total, DEVICE_MEMORY_SIZE
+                )),
+                Ok(_) => {}
+                Err(e) => problems.push(format!("{:#}", e)),
+            }
+        }
+    }
+
+    if let Some(patterns) = volca_sample.patterns.as_ref().filter(|_| valid) {
+        let default_reverb = volca_sample.default_part_reverb.unwrap_or(ToggleDef::Off);
+        let mut patterns: Vec<_> = patterns.iter().collect();
+        patterns.sort_by_key(|(index, _)| **index);
+        for (index, pattern_definition) in patterns {
+            if let Err(e) = parse_pattern_definition(*index, pattern_definition, default_reverb) {
+                problems.push(format!("patterns.{}: {:#}", index, e));
+            }
+        }
+    }
+    problems
+}
+
+// Steps of the parts in the pattern created by init
+const INIT_STEPS: [&str; 4] = [
+    "x... x... x... x...",
+    ".... x... .... x...",
+    "..x. ..x. ..x. ..x.",
+    ".... ...x .... ..x.",
+];
+
+/// Writes a starter configuration with every sample file in a directory, each commented with its
+/// duration and channels, and a pattern playing the first few samples. Json has no comments, so
+/// json files only get the samples and the pattern.
+fn init(dir: &str, format: ConfigFormat, output_file: Option<&str>) -> anyhow::Result<()> {
+    let default_output = Path::new(dir).join(format!("samples.{}", format.name()));
+    let output_file = output_file.map(PathBuf::from).unwrap_or(default_output);
+    let to_stdout = output_file == Path::new(STDIN);
+    if !to_stdout && output_file.exists() {
+        return Err(anyhow::anyhow!(
+            "File '{}' already exists",
+            output_file.to_string_lossy()
+        ));
+    }
+    // sample paths are relative to the configuration file
+    let output_dir = match to_stdout {
+        true => Path::new(""),
+        false => output_file.parent().unwrap_or(Path::new("")),
+    };
+
+    let mut paths = std::fs::read_dir(dir)
+        .with_context(|| format!("Cannot read directory '{}'", dir))?
+        .map(|entry| entry.map(|e| e.path()))
+        .collect::<Result<Vec<_>, _>>()?;
+    paths.retain(|path| path.is_file() && is_sample_file(path));
+    paths.sort();
+    // only the headers are read, files that can't be read are left out
+    let mut infos = Vec::new();
+    for path in paths {
+        match read_sample_info(&path) {
+            Ok((header, frames)) => infos.push((path, header, frames)),
+            Err(e) => warn!(
+                "Skipping '{}', it cannot be read: {:#}",
+                path.to_string_lossy(),
+                e
+            ),
+        }
+    }
+    if infos.len() > 100 {
+        warn!(
+            "Found {} sample files, only the first 100 fit in the sample slots",
+            infos.len()
+        );
+        infos.truncate(100);
+    }
+
+    let stem = |path: &Path| {
+        path.file_stem()
+            .map(|s| s.to_string_lossy().into_owned())
+            .unwrap_or_default()
+    };
+    let mut samples = HashMap::new();
+    let mut comments = Vec::new();
+    for (slot, (path, header, frames)) in (0..).zip(infos.iter()) {
+        let channels = match header.channel_count {
+            1 => String::from("mono"),
+            2 => String::from("stereo"),
+            n => format!("{} channels", n),
+        };
+        let file = match path.strip_prefix(output_dir) {
+            Ok(relative) => relative.to_path_buf(),
+            Err(_) => path.canonicalize()?,
+        };
+        let file = file.to_string_lossy().into_owned();
+        comments.push((
+            file.clone(),
+            format!(
+                "{:.3}s, {}, {} Hz, {} bit",
+                *frames as f64 / header.sampling_rate as f64,
+                channels,
+                header.sampling_rate,
+                header.bits_per_sample
+            ),
+        ));
+        // names are only given when no other file has the same one
+        let name = Some(stem(path)).filter(|name| {
+            !name.is_empty() && infos.iter().filter(|(p, _, _)| stem(p) == *name).count() == 1
+        });
+        samples.insert(
+            slot,
+            SampleAction::Sample(SampleDef {
+                file,
+                name,
+                compression: None,
+                channels: None,
+                resample: None,
+                start: None,
+                end: None,
+                trim_silence: None,
+                normalize: None,
+            }),
+        );
+    }
+
+    let parts: HashMap<u32, PartDef> = (0..)
+        .zip(INIT_STEPS.iter())
+        .take(samples.len())
+        .map(|(part, steps)| {
+            let sample = match &samples[&part] {
+                SampleAction::Sample(SampleDef {
+                    name: Some(name), ..
+                }) => SampleRefDef::Name(name.clone()),
+                _ => SampleRefDef::Slot(part),
+            };
+            let part_definition = PartDef {
+                sample: Some(sample),
+                steps: Some(StepsDef::Text(steps.to_string())),
+                ..Default::default()
+            };
+            (part, part_definition)
+        })
+        .collect();
+    let volca_sample = VolcaSample {
+        samples: Some(samples),
+        patterns: Some(vec![(0, PatternDef { parts })].into_iter().collect()),
+        ..Default::default()
+    };
+
+    let mut output = format.serialize(&volca_sample)?;
+    if let Some(prefix) = format.comment_prefix() {
+        let mut commented = String::new();
+        for line in output.lines() {
+            let trimmed = line.trim_start();
+            if let Some(value) = file_value(trimmed) {
+                let comment = comments.iter().find(|(file, _)| file == value);
+                if let Some((_, comment)) = comment {
+                    let indent = &line[..line.len() - trimmed.len()];
+                    commented.push_str(&format!("{}{} {}\n", indent, prefix, comment));
+                }
+            }
+            commented.push_str(line);
+            commented.push('\n');
+        }
+        output = commented;
+    }
+
+    if to_stdout {
+        stdout().write_all(output.as_bytes())?;
+    } else {
+        write(&output_file, output)
+            .with_context(|| format!("Cannot write file '{}'", output_file.to_string_lossy()))?;
+        info!("Wrote output to file '{}'", output_file.to_string_lossy());
+    }
+    Ok(())
+}
+
+/// Returns the value of a serialized `file` field line, in any of the config formats
+fn file_value(line: &str) -> Option<&str> {
+    let rest = line
+        .strip_prefix("\"file\"")
+        .or_else(|| line.strip_prefix("file"))?
+        .trim_start();
+    let value = rest
+        .strip_prefix(':')
+        .or_else(|| rest.strip_prefix('='))?
+        .trim()
+        .trim_end_matches(',');
+    Some(
+        value
+            .strip_prefix('"')
+            .and_then(|v| v.strip_suffix('"'))
+            .unwrap_or(value),
+    )
+}
+
+fn convert(
+    input_file: &str,
+    format: Option<ConfigFormat>,
+    output_file: Option<&str>,
+    output_format: Option<ConfigFormat>,
+) -> anyhow::Result<()> {
+    // includes and sample names are kept as they are
+    let (volca_sample, _) = read_config(input_file, format)?;
+    let output_format = output_format
+        .or_else(|| {
+            output_file
+                .map(Path::new)
+                .and_then(Path::extension)
+                .and_then(OsStr::to_str)
+                .and_then(ConfigFormat::from_name)
+        })
+        .ok_or_else(|| anyhow::anyhow!("Cannot infer output format, use --to to set it"))?;
+    let output = output_format.serialize(&volca_sample)?;
+    match output_file {
+        Some(output_file) if output_file != STDIN => {
+            write(output_file, output)
+                .with_context(|| format!("Cannot write file '{}'", output_file))?;
+            info!("Wrote output to file '{}'", output_file);
+        }
+        _ => stdout().write_all(output.as_bytes())?,
+    }
+    Ok(())
+}
+
 fn reset(input_file: &str, output_file: &str, compression: Option<u32>) -> anyhow::Result<()> {
     let data = read(input_file)?;
     let syro_out = SyroStream::reset(data, compression)?;
@@ -146,7 +934,7 @@ fn reset(input_file: &str, output_file: &str, compression: Option<u32>) -> anyho
         header
     );
 
-    let output = File::create(&output_file)?;
+    let output = File::create(output_file)?;
 
     wav::write(
         header,
@@ -162,7 +950,7 @@ fn compress_validator(v: String) -> Result<(), String> {
     let value = v
         .parse::<u32>()
         .map_err(|_| String::from("Cannot parse into u32"))?;
-    if value < 8 || value > 16 {
+    if !(8..=16).contains(&value) {
         Err(String::from("Invalid value, must be between 8 and 16"))
     } else {
         Ok(())
@@ -174,15 +962,69 @@ fn main() -> anyhow::Result<()> {
         .short("o")
         .long("out_file")
         .takes_value(true);
+    let format_arg = Arg::with_name("format")
+        .short("f")
+        .long("format")
+        .takes_value(true)
+        .possible_values(&ConfigFormat::NAMES)
+        .help("configuration format, inferred from the file extension or content if not set");
     let matches = App::new(clap::crate_name!())
         .about(clap::crate_description!())
         .version(clap::crate_version!())
         .setting(AppSettings::SubcommandRequiredElseHelp)
         .subcommand(
             SubCommand::with_name("load")
-                .about("Load sample configuration file")
+                .about("Load sample configuration file, use - to read from stdin")
+                .arg(Arg::with_name("input").required(true).index(1))
+                .arg(&output_arg)
+                .arg(&format_arg),
+        )
+        .subcommand(
+            SubCommand::with_name("plan")
+                .about("Estimate sample memory usage of a sample configuration file")
                 .arg(Arg::with_name("input").required(true).index(1))
-                .arg(&output_arg),
+                .arg(&format_arg),
+        )
+        .subcommand(
+            SubCommand::with_name("validate")
+                .about("Check sample configuration files without writing any output")
+                .arg(
+                    Arg::with_name("input")
+                        .required(true)
+                        .multiple(true)
+                        .index(1),
+                )
+                .arg(&format_arg),
+        )
+        .subcommand(
+            SubCommand::with_name("init")
+                .about("Create a sample configuration file with every sample file in a directory")
+                .arg(Arg::with_name("dir").required(true).index(1))
+                .arg(&output_arg)
+                .arg(
+                    Arg::with_name("format")
+                        .short("f")
+                        .long("format")
+                        .takes_value(true)
+                        .possible_values(&ConfigFormat::NAMES)
+                        .default_value("ron")
+                        .help("configuration format"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("convert")
+                .about("Convert a sample configuration file to another format")
+                .arg(Arg::with_name("input").required(true).index(1))
+                .arg(&output_arg)
+                .arg(&format_arg)
+                .arg(
+                    Arg::with_name("to")
+                        .short("t")
+                        .long("to")
+                        .takes_value(true)
+                        .possible_values(&ConfigFormat::NAMES)
+                        .help("output format, inferred from the output file extension if not set"),
+                ),
         )
         .subcommand(
             SubCommand::with_name("reset")
@@ -210,15 +1052,68 @@ fn main() -> anyhow::Result<()> {
         0 => log::LevelFilter::Warn,
         1 => log::LevelFilter::Info,
         2 => log::LevelFilter::Debug,
-        3 | _ => log::LevelFilter::Trace,
+        _ => log::LevelFilter::Trace,
     };
     SimpleLogger::new().with_level(log_level).init().unwrap();
 
     if let Some(matches) = matches.subcommand_matches("load") {
         let input_file = matches.value_of("input").unwrap();
         let output_file = get_output_file(matches, input_file);
+        let format = get_format(matches);
         info!("Loading from '{}'", input_file);
-        load(input_file, output_file.as_str())?;
+        load(input_file, format, output_file.as_str())?;
+    }
+
+    if let Some(matches) = matches.subcommand_matches("plan") {
+        let input_file = matches.value_of("input").unwrap();
+        let format = get_format(matches);
+        info!("Planning from '{}'", input_file);
+        plan(input_file, format)?;
+    }
+
+    if let Some(matches) = matches.subcommand_matches("validate") {
+        let input_files: Vec<&str> = matches.values_of("input").unwrap().collect();
+        let format = get_format(matches);
+        let mut invalid = 0;
+        for input_file in input_files.iter() {
+            info!("Validating '{}'", input_file);
+            let problems = check(input_file, format, &mut stdout());
+            if problems.is_empty() {
+                println!("{}: ok", input_file);
+            } else {
+                invalid += 1;
+                for problem in problems {
+                    println!("{}: {}", input_file, problem);
+                }
+            }
+        }
+        if invalid > 0 {
+            return Err(anyhow::anyhow!(
+                "{} of {} configuration files have problems",
+                invalid,
+                input_files.len()
+            ));
+        }
+    }
+
+    if let Some(matches) = matches.subcommand_matches("init") {
+        let dir = matches.value_of("dir").unwrap();
+        let format = get_format(matches).unwrap_or(ConfigFormat::Ron);
+        info!("Creating configuration from '{}'", dir);
+        init(dir, format, matches.value_of("output"))?;
+    }
+
+    if let Some(matches) = matches.subcommand_matches("convert") {
+        let input_file = matches.value_of("input").unwrap();
+        let format = get_format(matches);
+        let output_format = matches.value_of("to").and_then(ConfigFormat::from_name);
+        info!("Converting '{}'", input_file);
+        convert(
+            input_file,
+            format,
+            matches.value_of("output"),
+            output_format,
+        )?;
     }
 
     if let Some(matches) = matches.subcommand_matches("reset") {
@@ -234,3 +1129,452 @@ fn main() -> anyhow::Result<()> {
 
     Ok(())
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Creates an empty directory for a test below the system temporary directory
+    fn test_dir(name: &str) -> anyhow::Result<PathBuf> {
+        let dir = std::env::temp_dir().join(format!("vsrs-{}-{}", name, std::process::id()));
+        if dir.exists() {
+            std::fs::remove_dir_all(&dir)?;
+        }
+        std::fs::create_dir_all(&dir)?;
+        Ok(dir)
+    }
+
+    fn write_wav(path: &Path, channel_count: u16, frames: usize) -> anyhow::Result<()> {
+        let header = wav::Header::new(wav::WAV_FORMAT_PCM, channel_count, 31250, 16);
+        let data = wav::BitDepth::Sixteen(vec![0; frames * channel_count as usize]);
+        wav::write(header, &data, &mut File::create(path)?)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_prepare_silent_slice() -> anyhow::Result<()> {
+        let dir = test_dir("silent-slice")?;
+        let header = wav::Header::new(wav::WAV_FORMAT_PCM, 1, 31250, 16);
+        let mut data = vec![8000; 100];
+        data.extend(vec![0; 100]);
+        data.extend(vec![0; 50]);
+        data.extend(vec![8000; 50]);
+        let data = wav::BitDepth::Sixteen(data);
+        wav::write(header, &data, &mut File::create(dir.join("break.wav"))?)?;
+
+        let volca_sample = ConfigFormat::Yaml.deserialize(
+            r#"
+            default_trim_silence: -60
+            samples:
+              10:
+                Slices:
+                  file: break.wav
+                  count: 3
+            "#,
+        )?;
+        let slots = prepare_samples(&volca_sample, &dir)?;
+        let lengths: Vec<_> = slots
+            .iter()
+            .map(|(slot, content)| match content {
+                SlotContent::Sample(sample) => (*slot, sample.data.len()),
+                SlotContent::Erase => (*slot, 0),
+            })
+            .collect();
+        // the silent middle slice is kept untrimmed, the others are trimmed
+        assert_eq!(lengths, vec![(10, 100), (11, 100), (12, 50)]);
+
+        std::fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_file_value() {
+        assert_eq!(file_value("file: \"1.wav\","), Some("1.wav"));
+        assert_eq!(file_value("\"file\": \"1.wav\","), Some("1.wav"));
+        assert_eq!(file_value("file: 1.wav"), Some("1.wav"));
+        assert_eq!(file_value("file = \"1.wav\""), Some("1.wav"));
+        assert_eq!(file_value("files: 1.wav"), None);
+        assert_eq!(file_value("name: \"file\","), None);
+    }
+
+    #[test]
+    fn test_init() -> anyhow::Result<()> {
+        let dir = test_dir("init")?;
+        write_wav(&dir.join("1.wav"), 1, 31250)?;
+        write_wav(&dir.join("11.wav"), 2, 15625)?;
+        write(dir.join("notes.txt"), "not a sample")?;
+        write(dir.join("broken.wav"), "not a sample either")?;
+        let dir_name = dir.to_string_lossy();
+
+        for format in [ConfigFormat::Ron, ConfigFormat::Yaml, ConfigFormat::Toml].iter() {
+            init(&dir_name, *format, None)?;
+            let output_file = dir.join(format!("samples.{}", format.name()));
+            let output = read_to_string(&output_file)?;
+            let lines: Vec<_> = output.lines().map(str::trim).collect();
+            let comment_before = |file: &str| {
+                let index = lines
+                    .iter()
+                    .position(|line| file_value(line) == Some(file))
+                    .unwrap();
+                lines[index - 1]
+            };
+            let prefix = format.comment_prefix().unwrap();
+            assert_eq!(
+                comment_before("1.wav"),
+                format!("{} 1.000s, mono, 31250 Hz, 16 bit", prefix),
+                "{}",
+                format.name()
+            );
+            assert_eq!(
+                comment_before("11.wav"),
+                format!("{} 0.500s, stereo, 31250 Hz, 16 bit", prefix),
+                "{}",
+                format.name()
+            );
+
+            let volca_sample = format.deserialize(&output)?;
+            assert_eq!(volca_sample.samples.map(|samples| samples.len()), Some(2));
+            assert!(init(&dir_name, *format, None).is_err());
+        }
+
+        init(&dir_name, ConfigFormat::Json, None)?;
+        let output = read_to_string(dir.join("samples.json"))?;
+        let volca_sample = ConfigFormat::Json.deserialize(&output)?;
+        assert_eq!(volca_sample.samples.map(|samples| samples.len()), Some(2));
+
+        std::fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_check() -> anyhow::Result<()> {
+        let dir = test_dir("check")?;
+        write_wav(&dir.join("kick.wav"), 1, 3125)?;
+        let config = dir.join("samples.yaml");
+        write(
+            &config,
+            r#"
+            include: [ "missing/*.yaml" ]
+            sample_dirs:
+              - files: "drums/*.wav"
+            samples:
+              0:
+                Sample:
+                  file: kick.wav
+              1:
+                Sample:
+                  file: snare.wav
+            patterns:
+              0:
+                parts:
+                  0:
+                    sample: 0
+                    steps: [ 1, 0, 1, 0, 1, 0, 1, 0, 1, 0, 1, 0, 1, 0, 1, 0 ]
+                  1:
+                    sample: hat
+                    steps: [ 1, 0, 1, 0, 1, 0, 1, 0, 1, 0, 1, 0, 1, 0, 1, 0 ]
+            "#,
+        )?;
+        // problems with includes, sample_dirs and sample names don't stop the other checks
+        let problems = check(&config.to_string_lossy(), None, &mut Vec::new());
+        let sample_file = dir.join("snare.wav");
+        assert_eq!(
+            problems,
+            vec![
+                String::from("No configuration files match include 'missing/*.yaml'"),
+                String::from("No sample files match sample_dirs pattern 'drums/*.wav'"),
+                String::from("patterns.0.parts.1.sample: unknown sample name 'hat'"),
+                format!(
+                    "samples.1: Cannot read sample '{}': Cannot open input file: No such file or \
+                     directory (os error 2)",
+                    sample_file.display()
+                ),
+            ]
+        );
+
+        // the merged configuration is validated again after applying part templates, each of its
+        // problems is still reported once
+        write(
+            dir.join("more.yaml"),
+            r#"
+            samples:
+              2: Erase
+            "#,
+        )?;
+        let config = dir.join("merged.yaml");
+        write(
+            &config,
+            r#"
+            include: [ "more.yaml" ]
+            samples:
+              0:
+                Slices:
+                  file: kick.wav
+                  count: 4
+            "#,
+        )?;
+        let problems = check(&config.to_string_lossy(), None, &mut Vec::new());
+        assert_eq!(
+            problems,
+            vec!["samples.2: sample slot 2 is already used by samples.0"]
+        );
+
+        let problems = check(
+            &dir.join("missing.yaml").to_string_lossy(),
+            None,
+            &mut Vec::new(),
+        );
+        assert_eq!(problems.len(), 1);
+        assert!(problems[0].starts_with("Cannot open file"));
+
+        std::fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_expand_sample_dirs() -> anyhow::Result<()> {
+        let dir = test_dir("sample-dirs")?;
+        std::fs::create_dir(dir.join("drums"))?;
+        for file in ["c.wav", "a.wav", "b.wav", "notes.txt"].iter() {
+            write(dir.join("drums").join(file), "")?;
+        }
+        let expand = |config: &str| {
+            let mut data = ConfigFormat::Yaml.deserialize(config)?;
+            expand_sample_dirs(&mut data, &dir, &mut Vec::new())?;
+            let mut files: Vec<_> = data
+                .samples
+                .unwrap_or_default()
+                .into_iter()
+                .filter_map(|(slot, sample_action)| match sample_action {
+                    SampleAction::Sample(sample) => Some((slot, sample.file)),
+                    _ => None,
+                })
+                .collect();
+            files.sort();
+            Ok::<_, anyhow::Error>(files)
+        };
+        let files = |slots: &[u32]| -> Vec<(u32, String)> {
+            slots
+                .iter()
+                .zip(["a.wav", "b.wav", "c.wav"].iter())
+                .map(|(slot, file)| (*slot, format!("drums/{}", file)))
+                .collect()
+        };
+
+        // files go around occupied slots and the slots used by slices
+        let occupied = r#"
+            samples:
+              0:
+                Sample:
+                  file: kick.wav
+              2:
+                Slices:
+                  file: break.wav
+                  count: 3
+            sample_dirs:
+              - files: drums/*.wav
+                compression: 12
+        "#;
+        let mut expected = vec![(0, String::from("kick.wav"))];
+        expected.extend(files(&[1, 5, 6]));
+        assert_eq!(expand(occupied)?, expected);
+        let mut data = ConfigFormat::Yaml.deserialize(occupied)?;
+        expand_sample_dirs(&mut data, &dir, &mut Vec::new())?;
+        match &data.samples.unwrap()[&5] {
+            SampleAction::Sample(sample) => assert_eq!(sample.compression, Some(12)),
+            _ => panic!("expected a sample in slot 5"),
+        }
+
+        // an explicit slot puts the files in consecutive slots
+        let slot = "sample_dirs: [ { files: drums/*.wav, slot: 10 } ]";
+        assert_eq!(expand(slot)?, files(&[10, 11, 12]));
+
+        // and prints which file went to which slot
+        let mut output = Vec::new();
+        let mut data = ConfigFormat::Yaml.deserialize(slot)?;
+        expand_sample_dirs(&mut data, &dir, &mut output)?;
+        assert_eq!(
+            String::from_utf8(output)?,
+            "samples.10: drums/a.wav\nsamples.11: drums/b.wav\nsamples.12: drums/c.wav\n"
+        );
+
+        // but fails when one of them is taken
+        let collision = r#"
+            samples:
+              11:
+                Sample:
+                  file: kick.wav
+            sample_dirs: [ { files: drums/*.wav, slot: 10 } ]
+        "#;
+        let error = expand(collision).unwrap_err().to_string();
+        assert_eq!(
+            error,
+            "Sample slot 11 for 'drums/b.wav' of sample_dirs[0] is already used"
+        );
+        let twice =
+            "sample_dirs: [ { files: drums/*.wav, slot: 0 }, { files: drums/a.wav, slot: 2 } ]";
+        assert!(expand(twice).is_err());
+
+        // slots don't go past 99
+        let last = "sample_dirs: [ { files: drums/*.wav, slot: 98 } ]";
+        let error = expand(last).unwrap_err().to_string();
+        assert_eq!(
+            error,
+            "No free sample slot left for 'drums/c.wav' of sample_dirs[0]"
+        );
+        let full = r#"
+            samples:
+              0:
+                Slices:
+                  file: break.wav
+                  count: 97
+              98:
+                Sample:
+                  file: kick.wav
+            sample_dirs: [ { files: drums/*.wav } ]
+        "#;
+        let error = expand(full).unwrap_err().to_string();
+        assert_eq!(
+            error,
+            "No free sample slot left for 'drums/c.wav' of sample_dirs[0]"
+        );
+        let almost_full = full.replace("drums/*.wav", "\"drums/[ab].wav\"");
+        let mut expected = vec![(98, String::from("kick.wav"))];
+        expected.extend(files(&[97, 99]));
+        expected.sort();
+        assert_eq!(expand(&almost_full)?, expected);
+
+        assert!(expand("sample_dirs: [ { files: cymbals/*.wav } ]").is_err());
+        std::fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_include_configs() -> anyhow::Result<()> {
+        let dir = test_dir("include")?;
+        std::fs::create_dir_all(dir.join("kit").join("extra"))?;
+        let main = dir.join("main.yaml");
+        write(
+            &main,
+            r#"
+            include: [ "kit/*.ron" ]
+            samples:
+              0:
+                Sample:
+                  file: kick.wav
+            "#,
+        )?;
+        write(
+            dir.join("kit").join("kit.ron"),
+            r#"#![enable(implicit_some)]
+            (
+                include: ["extra/hats.toml"],
+                samples: {
+                    1: Sample((file: "snare.wav")),
+                    2: Slices((file: "loops/break.wav", count: 2)),
+                },
+                sample_dirs: [(files: "fx/*.wav")],
+            )"#,
+        )?;
+        write(
+            dir.join("kit").join("extra").join("hats.toml"),
+            r#"
+            [samples.4.Sample]
+            file = "hat.wav"
+            [samples.5.Sample]
+            file = "/samples/open_hat.wav"
+            "#,
+        )?;
+
+        let include = |file: &Path| {
+            let (mut data, _) = read_config(&file.to_string_lossy(), None)?;
+            let mut included_from = vec![file.canonicalize()?];
+            let mut merged = BTreeSet::new();
+            include_configs(
+                &mut data,
+                &dir,
+                Path::new(""),
+                &mut included_from,
+                &mut merged,
+            )?;
+            assert_eq!(included_from, vec![file.canonicalize()?]);
+            Ok::<_, anyhow::Error>(data)
+        };
+        let data = include(&main)?;
+        assert_eq!(data.include, None);
+        let mut files: Vec<_> = data
+            .samples
+            .unwrap()
+            .into_iter()
+            .map(|(slot, sample_action)| match sample_action {
+                SampleAction::Sample(SampleDef { file, .. })
+                | SampleAction::Slices(SlicesDef { file, .. }) => (slot, file),
+                SampleAction::Erase => (slot, String::new()),
+            })
+            .collect();
+        files.sort();
+        let expected_files = [
+            (0, Path::new("kick.wav").to_path_buf()),
+            (1, Path::new("kit").join("snare.wav")),
+            (2, Path::new("kit").join("loops/break.wav")),
+            (4, Path::new("kit").join("extra").join("hat.wav")),
+            (5, Path::new("/samples/open_hat.wav").to_path_buf()),
+        ];
+        let expected_files: Vec<_> = expected_files
+            .iter()
+            .map(|(slot, path)| (*slot, path.to_string_lossy().into_owned()))
+            .collect();
+        assert_eq!(files, expected_files);
+        let sample_dirs: Vec<_> = data
+            .sample_dirs
+            .unwrap()
+            .into_iter()
+            .map(|sample_dir| sample_dir.files)
+            .collect();
+        assert_eq!(
+            sample_dirs,
+            vec![Path::new("kit").join("fx/*.wav").to_string_lossy()]
+        );
+
+        // a file that includes itself, directly or through another file
+        write(&main, "include: [ main.yaml ]")?;
+        let error = include(&main).unwrap_err().to_string();
+        assert_eq!(
+            error,
+            format!("File '{}' includes itself", main.to_string_lossy())
+        );
+        write(&main, "include: [ kit/kit.ron ]")?;
+        write(
+            dir.join("kit").join("extra").join("hats.toml"),
+            "include = [ \"../../main.yaml\" ]",
+        )?;
+        let error = include(&main).unwrap_err().to_string();
+        assert!(error.ends_with("main.yaml' includes itself"), "{}", error);
+
+        // a file included through two others is only merged once
+        std::fs::create_dir(dir.join("diamond"))?;
+        write(&main, "include: [ diamond/b.yaml, diamond/c.yaml ]")?;
+        write(
+            dir.join("diamond").join("b.yaml"),
+            "{ include: [ d.yaml ], samples: { 1: { Sample: { file: b.wav } } } }",
+        )?;
+        write(
+            dir.join("diamond").join("c.yaml"),
+            "{ include: [ d.yaml ], samples: { 2: { Sample: { file: c.wav } } } }",
+        )?;
+        write(
+            dir.join("diamond").join("d.yaml"),
+            "{ samples: { 3: { Sample: { file: d.wav } } } }",
+        )?;
+        let mut slots: Vec<_> = include(&main)?.samples.unwrap().into_keys().collect();
+        slots.sort_unstable();
+        assert_eq!(slots, vec![1, 2, 3]);
+
+        // a missing include
+        write(&main, "include: [ drums/*.yaml ]")?;
+        assert!(include(&main).is_err());
+
+        std::fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,115 @@
+// The volca sample plays back samples at 31.25 kHz
+pub const DEVICE_SAMPLING_RATE: u32 = 31250;
+
+// The sample memory holds 65 seconds of 16 bit audio at the device sampling rate
+pub const DEVICE_MEMORY_SIZE: u64 = 65 * DEVICE_SAMPLING_RATE as u64 * 2;
+
+/// Number of frames a sample takes up once stored at the device sampling rate
+pub fn device_frames(frames: usize, sampling_rate: u32) -> anyhow::Result<u64> {
+    if sampling_rate == 0 {
+        return Err(anyhow::anyhow!("Invalid sampling rate of 0 Hz"));
+    }
+    Ok((frames as u64 * DEVICE_SAMPLING_RATE as u64).div_ceil(sampling_rate as u64))
+}
+
+/// Estimated number of bytes a sample takes up in sample memory,
+/// compressed samples are stored using `compression` bits per frame
+pub fn estimated_size(device_frames: u64, compression: Option<u32>) -> u64 {
+    (device_frames * compression.unwrap_or(16) as u64).div_ceil(8)
+}
+
+// A sample whose compression may be lowered to fit in sample memory
+pub struct CompressionCandidate {
+    pub device_frames: u64,
+    pub compression: u32,
+    // Number of pattern parts using the sample
+    pub references: usize,
+}
+
+/// Finds compression bit depths for `candidates` so that they fit in sample memory together
+/// with `fixed_size` bytes of other samples. Bit depths are lowered one step at a time, starting
+/// with the least referenced samples, and within those the ones with the highest bit depth.
+pub fn fit_compression(
+    fixed_size: u64,
+    candidates: &[CompressionCandidate],
+) -> anyhow::Result<Vec<u32>> {
+    let mut compression: Vec<u32> = candidates.iter().map(|c| c.compression).collect();
+    let total_size = |compression: &[u32]| {
+        fixed_size
+            + candidates
+                .iter()
+                .zip(compression)
+                .map(|(c, bits)| estimated_size(c.device_frames, Some(*bits)))
+                .sum::<u64>()
+    };
+
+    let mut reference_counts: Vec<usize> = candidates.iter().map(|c| c.references).collect();
+    reference_counts.sort_unstable();
+    reference_counts.dedup();
+    for references in reference_counts {
+        while total_size(&compression) > DEVICE_MEMORY_SIZE {
+            let lowest_priority = (0..candidates.len())
+                .filter(|&i| candidates[i].references == references && compression[i] > 8)
+                .max_by_key(|&i| compression[i]);
+            match lowest_priority {
+                Some(i) => compression[i] -= 1,
+                None => break,
+            }
+        }
+    }
+
+    let total = total_size(&compression);
+    if total > DEVICE_MEMORY_SIZE {
+        return Err(anyhow::anyhow!(
+            "Samples need at least {} bytes, which exceeds the {} bytes of sample memory",
+            total,
+            DEVICE_MEMORY_SIZE
+        ));
+    }
+    Ok(compression)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_estimated_size() -> anyhow::Result<()> {
+        assert_eq!(device_frames(31250, DEVICE_SAMPLING_RATE)?, 31250);
+        assert_eq!(device_frames(44100, 44100)?, 31250);
+        assert_eq!(device_frames(1, 96000)?, 1);
+        assert!(device_frames(1, 0).is_err());
+
+        assert_eq!(estimated_size(31250, None), 62500);
+        assert_eq!(estimated_size(31250, Some(16)), 62500);
+        assert_eq!(estimated_size(31250, Some(8)), 31250);
+        assert_eq!(estimated_size(3, Some(12)), 5);
+        Ok(())
+    }
+
+    #[test]
+    fn test_fit_compression() -> anyhow::Result<()> {
+        let seconds = |s: u64| s * DEVICE_SAMPLING_RATE as u64;
+        let candidate = |seconds, references| CompressionCandidate {
+            device_frames: seconds,
+            compression: 16,
+            references,
+        };
+
+        // fits already
+        let fits = [candidate(seconds(30), 0), candidate(seconds(30), 1)];
+        assert_eq!(fit_compression(0, &fits)?, vec![16, 16]);
+
+        // the unreferenced sample is compressed first
+        let too_big = [candidate(seconds(40), 0), candidate(seconds(40), 3)];
+        assert_eq!(fit_compression(0, &too_big)?, vec![10, 16]);
+
+        // both are compressed once the unreferenced sample can't go any lower
+        let much_too_big = [candidate(seconds(50), 0), candidate(seconds(50), 3)];
+        assert_eq!(fit_compression(0, &much_too_big)?, vec![8, 12]);
+
+        assert!(fit_compression(0, &[candidate(seconds(140), 0)]).is_err());
+        assert!(fit_compression(DEVICE_MEMORY_SIZE, &fits).is_err());
+        Ok(())
+    }
+}
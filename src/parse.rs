@@ -1,22 +1,130 @@
-use std::collections::HashMap;
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, HashMap};
 use std::convert::TryInto;
+use std::f64::consts::PI;
+use std::fmt;
+use std::hash::Hash;
+use std::ops::RangeInclusive;
 
 use anyhow::Context;
 use korg_syro::{pattern, pattern::num_enum::TryFromPrimitive};
 use log::{debug, trace};
-use serde::{Deserialize, Serialize};
+use serde::{de, ser::SerializeMap, Deserialize, Deserializer, Serialize, Serializer};
 
 #[derive(Debug, PartialEq, Serialize, Deserialize)]
 #[serde(deny_unknown_fields)]
 pub struct SampleDef {
     pub file: String,
+    // Name that parts can use instead of the slot number
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub compression: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub channels: Option<ChannelsDef>,
+    // Sampling rate to resample to before adding the sample
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub resample: Option<u32>,
+    // Region of the file to use
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub start: Option<OffsetDef>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub end: Option<OffsetDef>,
+    // Threshold in dBFS below which leading and trailing audio is removed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub trim_silence: Option<f64>,
+    // Peak level in dBFS to normalize to
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub normalize: Option<f64>,
+}
+
+// Position in a sample file
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+#[serde(rename_all = "lowercase")]
+pub enum OffsetDef {
+    Frames(u32),
+    Seconds(f64),
+    Milliseconds(f64),
+}
+
+// How multichannel input is turned into mono
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+#[serde(rename_all = "lowercase")]
+pub enum ChannelsDef {
+    // Average of all channels
+    Mix,
+    Left,
+    Right,
+    // (left + right) / 2
+    Mid,
+    // (left - right) / 2
+    Side,
+}
+
+// Splits one file into slices which are put in consecutive sample slots
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SlicesDef {
+    pub file: String,
+    // Either the number of equally long slices, or the offsets to cut the file at
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub count: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub markers: Option<Vec<OffsetDef>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub compression: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub channels: Option<ChannelsDef>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub resample: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub trim_silence: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub normalize: Option<f64>,
+}
+
+impl SlicesDef {
+    pub fn slot_count(&self) -> anyhow::Result<u32> {
+        match (self.count, &self.markers) {
+            (Some(count), None) if count > 0 => Ok(count),
+            (None, Some(markers)) => Ok(markers.len() as u32 + 1),
+            _ => Err(anyhow::anyhow!(
+                "Slices of '{}' need either a non-zero count or markers",
+                self.file
+            )),
+        }
+    }
+}
+
+// Adds every sample file matching a glob pattern, in file name order
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SampleDirDef {
+    // Glob pattern relative to the configuration file, e.g. "drums/*.wav"
+    pub files: String,
+    // Slot of the first file, the others go in the following slots. If not given the files go in
+    // the first free slots.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub slot: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub compression: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub channels: Option<ChannelsDef>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub resample: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub trim_silence: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub normalize: Option<f64>,
 }
 
 #[derive(Debug, PartialEq, Serialize, Deserialize)]
 #[serde(deny_unknown_fields)]
 pub enum SampleAction {
     Sample(SampleDef),
+    Slices(SlicesDef),
     Erase,
 }
 
@@ -28,87 +136,1122 @@ pub enum ToggleDef {
     Off,
 }
 
-impl Into<korg_syro::pattern::Toggle> for ToggleDef {
-    fn into(self) -> korg_syro::pattern::Toggle {
+impl From<ToggleDef> for korg_syro::pattern::Toggle {
+    fn from(toggle: ToggleDef) -> Self {
         use korg_syro::pattern::Toggle;
-        match self {
+        match toggle {
             ToggleDef::On => Toggle::On,
             ToggleDef::Off => Toggle::Off,
         }
     }
 }
 
-#[derive(Debug, PartialEq, Serialize, Deserialize)]
+// Sample used by a part, either a slot number or the name of a sample
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum SampleRefDef {
+    Slot(u32),
+    Name(String),
+}
+
+pub const STEP_COUNT: usize = 16;
+
+// A single step in a list, 0 or false is off and 1 or true is on
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum StepDef {
+    Toggle(bool),
+    Value(u32),
+}
+
+impl StepDef {
+    fn state(self) -> anyhow::Result<bool> {
+        match self {
+            StepDef::Toggle(on) => Ok(on),
+            StepDef::Value(0) => Ok(false),
+            StepDef::Value(1) => Ok(true),
+            StepDef::Value(value) => Err(anyhow::anyhow!(
+                "invalid step {}, use 1 or true for on and 0 or false for off",
+                value
+            )),
+        }
+    }
+}
+
+// Sequence steps, either a list of steps, or a string where x or 1 is on and . or 0 is off,
+// ignoring spaces and |
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum StepsDef {
+    List(Vec<StepDef>),
+    Text(String),
+}
+
+impl StepsDef {
+    /// Whether each step is on, there must be exactly 16 steps
+    pub fn states(&self) -> anyhow::Result<[bool; STEP_COUNT]> {
+        let states: Vec<bool> = match self {
+            StepsDef::List(steps) => steps
+                .iter()
+                .map(|step| step.state())
+                .collect::<anyhow::Result<_>>()?,
+            StepsDef::Text(text) => text
+                .chars()
+                .filter(|c| !c.is_whitespace() && *c != '|')
+                .map(|c| match c {
+                    'x' | 'X' | '1' => Ok(true),
+                    '.' | '-' | '_' | '0' => Ok(false),
+                    _ => Err(anyhow::anyhow!(
+                        "invalid step '{}', use x or 1 for on and . or 0 for off",
+                        c
+                    )),
+                })
+                .collect::<anyhow::Result<_>>()?,
+        };
+        let count = states.len();
+        states.try_into().map_err(|_| {
+            anyhow::anyhow!("{} steps given, a pattern has {} steps", count, STEP_COUNT)
+        })
+    }
+}
+
+// Indexes 0-15 of the steps that are on
+fn hits_to_states(hits: &[u32]) -> anyhow::Result<[bool; STEP_COUNT]> {
+    let mut states = [false; STEP_COUNT];
+    for hit in hits {
+        match states.get_mut(*hit as usize) {
+            Some(state) => *state = true,
+            None => {
+                return Err(anyhow::anyhow!(
+                    "hit {} is out of range 0-{}",
+                    hit,
+                    STEP_COUNT - 1
+                ))
+            }
+        }
+    }
+    Ok(states)
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
 #[serde(deny_unknown_fields)]
 pub struct PartDef {
-    pub sample: u32,
-    pub steps: Vec<u32>,
+    // Name of a part template to take the fields left out here from
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub template: Option<String>,
+    // Required, unless given by the template
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sample: Option<SampleRefDef>,
+    // Either steps or the indexes of the steps that are on
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub steps: Option<StepsDef>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hits: Option<Vec<u32>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub motion: Option<ToggleDef>,
-    #[serde(rename = "loop")]
+    #[serde(skip_serializing_if = "Option::is_none", rename = "loop")]
     pub looped: Option<ToggleDef>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub reverb: Option<ToggleDef>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub reverse: Option<ToggleDef>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub mute: Option<ToggleDef>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub level: Option<u8>,
-    pub pan: Option<u8>,
-    pub speed: Option<u8>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pan: Option<PanDef>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub speed: Option<SpeedDef>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub amp_eg_attack: Option<u8>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub amp_eg_decay: Option<u8>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub pitch_eg_attack: Option<u8>,
-    pub pitch_eg_int: Option<u8>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pitch_eg_int: Option<PitchEgIntDef>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub pitch_eg_decay: Option<u8>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub starting_point: Option<u8>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub length: Option<u8>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub hi_cut: Option<u8>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub motion_sequences: Option<MotionSequencesDef>,
 }
 
-#[derive(Debug, PartialEq, Serialize, Deserialize)]
+// Raw values of the center of pan, pitch_eg_int and speed
+const CENTER: i64 = 64;
+const SPEED_CONTINUOUS_CENTER: f64 = 192.0;
+const SEMITONES: RangeInclusive<i64> = -24..=24;
+const OFFSET: RangeInclusive<i64> = -63..=63;
+
+// Pan, either the raw value 1-127 where 64 is center, or "C", "L1"-"L63" or "R1"-"R63"
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum PanDef {
+    Value(u8),
+    Position(String),
+}
+
+impl PanDef {
+    /// The raw value, positions are checked but raw values are not
+    pub fn value(&self) -> anyhow::Result<u8> {
+        let text = match self {
+            PanDef::Value(value) => return Ok(*value),
+            PanDef::Position(text) => text.trim(),
+        };
+        let invalid = || anyhow::anyhow!("invalid pan '{}', use C, L1-L63 or R1-R63", text);
+        let position = text.to_uppercase();
+        let mut chars = position.chars();
+        let (side, amount) = match (chars.next(), chars.as_str()) {
+            (Some('C'), "") => return Ok(CENTER as u8),
+            (Some('L'), amount) => (-1, amount),
+            (Some('R'), amount) => (1, amount),
+            _ => return Err(invalid()),
+        };
+        match amount.parse::<i64>() {
+            Ok(amount) if OFFSET.contains(&amount) => Ok((CENTER + side * amount) as u8),
+            _ => Err(invalid()),
+        }
+    }
+}
+
+// Speed, either the raw value 40-88 (semitone) or 129-255 (continuous), or "semitones(+3)" for
+// -24 to +24 semitones, or "continuous(-0.25)" for -1.0 to 1.0 of the continuous range
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum SpeedDef {
+    Value(u8),
+    Text(String),
+}
+
+impl SpeedDef {
+    /// The raw value, text is checked but raw values are not
+    pub fn value(&self) -> anyhow::Result<u8> {
+        let text = match self {
+            SpeedDef::Value(value) => return Ok(*value),
+            SpeedDef::Text(text) => text,
+        };
+        let invalid = || {
+            anyhow::anyhow!(
+                "invalid speed '{}', use semitones(-24 to +24) or continuous(-1.0 to 1.0)",
+                text.trim()
+            )
+        };
+        let (name, args) = parse_generator(text).map_err(|_| invalid())?;
+        match (name, args.as_slice()) {
+            ("semitones", [semitones])
+                if semitones.fract() == 0.0 && SEMITONES.contains(&(*semitones as i64)) =>
+            {
+                Ok((CENTER + *semitones as i64) as u8)
+            }
+            ("continuous", [amount]) if (-1.0..=1.0).contains(amount) => {
+                let range = f64::from(*SPEED_CONTINUOUS.end()) - SPEED_CONTINUOUS_CENTER;
+                Ok((SPEED_CONTINUOUS_CENTER + amount * range).round() as u8)
+            }
+            _ => Err(invalid()),
+        }
+    }
+}
+
+// Pitch EG intensity, a number is the raw value 1-127 where 64 is center when it's positive and
+// an offset from the center when it's negative, offsets from -63 to +63 can also be given as a
+// signed string like "+12" or "-12"
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum PitchEgIntDef {
+    Value(i64),
+    Offset(String),
+}
+
+impl PitchEgIntDef {
+    /// The raw value, both raw values and offsets are checked
+    pub fn value(&self) -> anyhow::Result<u8> {
+        let offset = match self {
+            PitchEgIntDef::Value(offset) if *offset < 0 => *offset,
+            PitchEgIntDef::Value(value) => {
+                let raw: Option<u8> = (*value).try_into().ok();
+                return raw
+                    .filter(|raw| CENTERED_PARAMETER.contains(raw))
+                    .ok_or_else(|| {
+                        anyhow::anyhow!(
+                            "value {} is out of range {}-{}",
+                            value,
+                            CENTERED_PARAMETER.start(),
+                            CENTERED_PARAMETER.end()
+                        )
+                    });
+            }
+            PitchEgIntDef::Offset(text) => {
+                let text = text.trim();
+                let signed = text.starts_with('+') || text.starts_with('-');
+                text.parse().ok().filter(|_| signed).ok_or_else(|| {
+                    anyhow::anyhow!(
+                        "invalid pitch_eg_int '{}', use an offset like \"+12\" or \"-12\"",
+                        text
+                    )
+                })?
+            }
+        };
+        if OFFSET.contains(&offset) {
+            Ok((CENTER + offset) as u8)
+        } else {
+            Err(anyhow::anyhow!(
+                "offset {} is out of range {} to +{}",
+                offset,
+                OFFSET.start(),
+                OFFSET.end()
+            ))
+        }
+    }
+}
+
+pub const MOTION_SEQUENCE_LENGTH: usize = 16;
+
+// Valid values of the part parameters and motion sequences
+pub const PARAMETER: RangeInclusive<u8> = 0..=127;
+pub const CENTERED_PARAMETER: RangeInclusive<u8> = 1..=127;
+pub const SPEED_SEMITONE: RangeInclusive<u8> = 40..=88;
+pub const SPEED_CONTINUOUS: RangeInclusive<u8> = 129..=255;
+
+// Motion sequence values, either a list of 16 values, a generator like "ramp(0, 127)", or a
+// shorter list of values with a mode
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(untagged)]
+pub enum MotionSequenceDef {
+    Values(Vec<u8>),
+    Generator(String),
+    Short(ShortSequenceDef),
+}
+
+// Deserialized by hand rather than untagged, so that in RON the mode can be written without quotes
+impl<'de> Deserialize<'de> for MotionSequenceDef {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct MotionSequenceVisitor;
+
+        impl<'de> de::Visitor<'de> for MotionSequenceVisitor {
+            type Value = MotionSequenceDef;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("a list of values, a generator, or values with a mode")
+            }
+
+            fn visit_str<E: de::Error>(self, value: &str) -> Result<MotionSequenceDef, E> {
+                Ok(MotionSequenceDef::Generator(value.to_string()))
+            }
+
+            fn visit_seq<A: de::SeqAccess<'de>>(
+                self,
+                seq: A,
+            ) -> Result<MotionSequenceDef, A::Error> {
+                Vec::deserialize(de::value::SeqAccessDeserializer::new(seq))
+                    .map(MotionSequenceDef::Values)
+            }
+
+            fn visit_map<A: de::MapAccess<'de>>(
+                self,
+                map: A,
+            ) -> Result<MotionSequenceDef, A::Error> {
+                ShortSequenceDef::deserialize(de::value::MapAccessDeserializer::new(map))
+                    .map(MotionSequenceDef::Short)
+            }
+        }
+
+        deserializer.deserialize_any(MotionSequenceVisitor)
+    }
+}
+
+// Up to 16 values spread over the motion sequence
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ShortSequenceDef {
+    pub values: Vec<u8>,
+    pub mode: SequenceModeDef,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+#[serde(rename_all = "lowercase")]
+pub enum SequenceModeDef {
+    // The values are repeated until there are 16
+    Repeat,
+    // The values are keyframes spread evenly over the 16 steps, with the steps between them
+    // changing linearly
+    Interpolate,
+}
+
+impl ShortSequenceDef {
+    /// The 16 values of the sequence, without checking their range
+    pub fn expand(&self) -> anyhow::Result<[u8; 16]> {
+        let count = self.values.len();
+        if count == 0 || count > MOTION_SEQUENCE_LENGTH {
+            return Err(anyhow::anyhow!(
+                "{} values given, a short motion sequence has 1-{} values",
+                count,
+                MOTION_SEQUENCE_LENGTH
+            ));
+        }
+        let steps = 0..MOTION_SEQUENCE_LENGTH;
+        let values: Vec<u8> = match self.mode {
+            SequenceModeDef::Repeat => steps.map(|i| self.values[i % count]).collect(),
+            SequenceModeDef::Interpolate if count == 1 => steps.map(|_| self.values[0]).collect(),
+            SequenceModeDef::Interpolate => {
+                // position of the step between the keyframes
+                let scale = (count - 1) as f64 / (MOTION_SEQUENCE_LENGTH - 1) as f64;
+                steps
+                    .map(|i| {
+                        let position = i as f64 * scale;
+                        let before = position.floor() as usize;
+                        let after = (before + 1).min(count - 1);
+                        let (from, to) = (
+                            f64::from(self.values[before]),
+                            f64::from(self.values[after]),
+                        );
+                        (from + (to - from) * position.fract()).round() as u8
+                    })
+                    .collect()
+            }
+        };
+        values.as_slice().into_motion_seq()
+    }
+}
+
+// Speeds are either semitones or continuous, which can't be mixed in a motion sequence
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum SpeedMode {
+    Semitone,
+    Continuous,
+}
+
+impl SpeedMode {
+    pub fn of(speed: u8) -> Option<SpeedMode> {
+        if SPEED_SEMITONE.contains(&speed) {
+            Some(SpeedMode::Semitone)
+        } else if SPEED_CONTINUOUS.contains(&speed) {
+            Some(SpeedMode::Continuous)
+        } else {
+            None
+        }
+    }
+
+    pub fn range(self) -> RangeInclusive<u8> {
+        match self {
+            SpeedMode::Semitone => SPEED_SEMITONE,
+            SpeedMode::Continuous => SPEED_CONTINUOUS,
+        }
+    }
+}
+
+impl fmt::Display for SpeedMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let range = self.range();
+        match self {
+            SpeedMode::Semitone => write!(f, "semitone ({}-{})", range.start(), range.end()),
+            SpeedMode::Continuous => write!(f, "continuous ({}-{})", range.start(), range.end()),
+        }
+    }
+}
+
+/// Checks that the speeds of a motion sequence are all in the same mode, which is taken to be the
+/// mode of most values, or of the first value if there is a tie. Speeds in neither mode are
+/// ignored.
+pub fn check_speed_modes(speeds: &[u8]) -> anyhow::Result<()> {
+    let modes: Vec<(usize, SpeedMode)> = speeds
+        .iter()
+        .enumerate()
+        .filter_map(|(i, speed)| SpeedMode::of(*speed).map(|mode| (i, mode)))
+        .collect();
+    let continuous = modes
+        .iter()
+        .filter(|(_, mode)| *mode == SpeedMode::Continuous)
+        .count();
+    let intended = match (continuous * 2).cmp(&modes.len()) {
+        Ordering::Greater => SpeedMode::Continuous,
+        Ordering::Less => SpeedMode::Semitone,
+        Ordering::Equal => match modes.first() {
+            Some((_, mode)) => *mode,
+            None => return Ok(()),
+        },
+    };
+    let mixed: Vec<String> = modes
+        .iter()
+        .filter(|(_, mode)| *mode != intended)
+        .map(|(i, _)| format!("[{}]", i))
+        .collect();
+    if mixed.is_empty() {
+        return Ok(());
+    }
+    let other = match intended {
+        SpeedMode::Semitone => SpeedMode::Continuous,
+        SpeedMode::Continuous => SpeedMode::Semitone,
+    };
+    Err(anyhow::anyhow!(
+        "semitone and continuous speeds can't be mixed, the sequence is {} but {} {} {}",
+        intended,
+        mixed.join(", "),
+        if mixed.len() == 1 { "is" } else { "are" },
+        other
+    ))
+}
+
+// Checks values against the range of a motion sequence, or for speed (no range) that they are
+// all semitone or all continuous speeds
+fn check_motion_values(values: &[u8], range: Option<&RangeInclusive<u8>>) -> anyhow::Result<()> {
+    for (i, value) in values.iter().enumerate() {
+        match range {
+            Some(range) if !range.contains(value) => {
+                return Err(anyhow::anyhow!(
+                    "value {} at [{}] is out of range {}-{}",
+                    value,
+                    i,
+                    range.start(),
+                    range.end()
+                ))
+            }
+            None if SpeedMode::of(*value).is_none() => {
+                return Err(anyhow::anyhow!(
+                    "value {} at [{}] is out of range, valid values are {} or {}",
+                    value,
+                    i,
+                    SpeedMode::Semitone,
+                    SpeedMode::Continuous
+                ))
+            }
+            _ => {}
+        }
+    }
+    match range {
+        Some(_) => Ok(()),
+        None => check_speed_modes(values),
+    }
+}
+
+impl MotionSequenceDef {
+    /// The 16 values of the sequence, checked against `range`, or for speed (no range) that they
+    /// don't mix semitone and continuous speeds. Generated values are clamped to the range, generated
+    /// speeds are checked like the listed ones.
+    pub fn expand(&self, range: Option<&RangeInclusive<u8>>) -> anyhow::Result<[u8; 16]> {
+        let generator = match self {
+            MotionSequenceDef::Values(values) => {
+                check_motion_values(values, range)?;
+                return values.as_slice().into_motion_seq();
+            }
+            MotionSequenceDef::Short(short) => {
+                // values between the keyframes are in range when the keyframes are
+                check_motion_values(&short.values, range)?;
+                return short.expand();
+            }
+            MotionSequenceDef::Generator(generator) => generator,
+        };
+        let (name, args) = parse_generator(generator)?;
+        let arg_count = |names: &str| -> anyhow::Result<()> {
+            let expected = names.split(", ").count();
+            if args.len() == expected {
+                Ok(())
+            } else {
+                Err(anyhow::anyhow!(
+                    "{} takes {} arguments ({}), {} given",
+                    name,
+                    expected,
+                    names,
+                    args.len()
+                ))
+            }
+        };
+        let clamp = match range {
+            Some(range) => range.clone(),
+            None if args
+                .first()
+                .is_some_and(|arg| *arg >= f64::from(*SPEED_CONTINUOUS.start())) =>
+            {
+                SPEED_CONTINUOUS
+            }
+            None => SPEED_SEMITONE,
+        };
+        let (min, max) = (f64::from(*clamp.start()), f64::from(*clamp.end()));
+        let steps = 0..MOTION_SEQUENCE_LENGTH;
+        let values: Vec<f64> = match name {
+            "ramp" => {
+                arg_count("from, to")?;
+                let (from, to) = (args[0], args[1]);
+                let last = (MOTION_SEQUENCE_LENGTH - 1) as f64;
+                steps
+                    .map(|i| from + (to - from) * i as f64 / last)
+                    .collect()
+            }
+            "triangle" => {
+                let (low, high) = if args.is_empty() {
+                    (min, max)
+                } else {
+                    arg_count("min, max")?;
+                    (args[0], args[1])
+                };
+                let half = (MOTION_SEQUENCE_LENGTH / 2) as f64;
+                steps
+                    .map(|i| low + (high - low) * (1.0 - (i as f64 - half).abs() / half))
+                    .collect()
+            }
+            "sine" => {
+                arg_count("min, max, cycles")?;
+                let (low, high, cycles) = (args[0], args[1], args[2]);
+                let length = MOTION_SEQUENCE_LENGTH as f64;
+                steps
+                    .map(|i| {
+                        let phase = 2.0 * PI * cycles * i as f64 / length;
+                        low + (high - low) * (1.0 + phase.sin()) / 2.0
+                    })
+                    .collect()
+            }
+            "random" => {
+                arg_count("min, max, seed")?;
+                let (low, high) = (args[0], args[1]);
+                let mut state = args[2] as u64;
+                steps
+                    .map(|_| low + (high - low) * split_mix(&mut state))
+                    .collect()
+            }
+            "hold" => {
+                arg_count("value")?;
+                steps.map(|_| args[0]).collect()
+            }
+            _ => {
+                return Err(anyhow::anyhow!(
+                    "unknown generator '{}', use ramp, triangle, sine, random or hold",
+                    name
+                ))
+            }
+        };
+        if range.is_none() {
+            // the cycles of sine and seed of random aren't speeds
+            let speeds = match name {
+                "sine" | "random" => &args[..2],
+                _ => &args[..],
+            };
+            let continuous = f64::from(*SPEED_CONTINUOUS.start());
+            if speeds
+                .iter()
+                .any(|speed| (*speed >= continuous) != (args[0] >= continuous))
+            {
+                return Err(anyhow::anyhow!(
+                    "{} mixes semitone and continuous speeds, use either {} or {}",
+                    generator.trim(),
+                    SpeedMode::Semitone,
+                    SpeedMode::Continuous
+                ));
+            }
+        }
+        let values: Vec<u8> = match range {
+            Some(_) => values
+                .iter()
+                .map(|value| value.round().max(min).min(max) as u8)
+                .collect(),
+            // a speed between the semitone and continuous ranges has no closest valid speed
+            None => {
+                let speeds = values
+                    .iter()
+                    .enumerate()
+                    .map(|(i, value)| match value.round() {
+                        speed if (0.0..=255.0).contains(&speed) => Ok(speed as u8),
+                        speed => Err(anyhow::anyhow!(
+                            "value {} at [{}] is out of range, valid values are {} or {}",
+                            speed,
+                            i,
+                            SpeedMode::Semitone,
+                            SpeedMode::Continuous
+                        )),
+                    })
+                    .collect::<anyhow::Result<Vec<u8>>>()?;
+                check_motion_values(&speeds, None)?;
+                speeds
+            }
+        };
+        values.as_slice().into_motion_seq()
+    }
+}
+
+impl MotionSequencesDef {
+    fn inherit(&mut self, template: &MotionSequencesDef) {
+        inherit(&mut self.level_start, &template.level_start);
+        inherit(&mut self.level_end, &template.level_end);
+        inherit(&mut self.pan_start, &template.pan_start);
+        inherit(&mut self.pan_end, &template.pan_end);
+        inherit(&mut self.speed_start, &template.speed_start);
+        inherit(&mut self.speed_end, &template.speed_end);
+        inherit(&mut self.amp_eg_attack, &template.amp_eg_attack);
+        inherit(&mut self.amp_eg_decay, &template.amp_eg_decay);
+        inherit(&mut self.pitch_eg_int, &template.pitch_eg_int);
+        inherit(&mut self.pitch_eg_attack, &template.pitch_eg_attack);
+        inherit(&mut self.pitch_eg_decay, &template.pitch_eg_decay);
+        inherit(&mut self.start_point, &template.start_point);
+        inherit(&mut self.length, &template.length);
+        inherit(&mut self.hi_cut, &template.hi_cut);
+    }
+}
+
+fn inherit<T: Clone>(field: &mut Option<T>, template: &Option<T>) {
+    if field.is_none() {
+        *field = template.clone();
+    }
+}
+
+// Splits a generator like "sine(0, 127, 2)" into its name and arguments
+fn parse_generator(generator: &str) -> anyhow::Result<(&str, Vec<f64>)> {
+    let generator = generator.trim();
+    let (name, args) = match generator.find('(') {
+        Some(open) if generator.ends_with(')') => (
+            &generator[..open],
+            &generator[open + 1..generator.len() - 1],
+        ),
+        Some(_) => return Err(anyhow::anyhow!("missing ) in generator '{}'", generator)),
+        None => (generator, ""),
+    };
+    let args = args
+        .split(',')
+        .map(str::trim)
+        .filter(|arg| !arg.is_empty())
+        .map(|arg| {
+            arg.parse::<f64>().map_err(|_| {
+                anyhow::anyhow!("invalid number '{}' in generator '{}'", arg, generator)
+            })
+        })
+        .collect::<anyhow::Result<_>>()?;
+    Ok((name.trim(), args))
+}
+
+// Next pseudo random number in 0-1 from a SplitMix64 generator, so seeds give the same sequence
+// on every platform
+fn split_mix(state: &mut u64) -> f64 {
+    *state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    let mut z = *state;
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^= z >> 31;
+    (z >> 11) as f64 / (1u64 << 53) as f64
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 #[serde(deny_unknown_fields)]
 pub struct MotionSequencesDef {
-    pub level_start: Option<Vec<u8>>,
-    pub level_end: Option<Vec<u8>>,
-    pub pan_start: Option<Vec<u8>>,
-    pub pan_end: Option<Vec<u8>>,
-    pub speed_start: Option<Vec<u8>>,
-    pub speed_end: Option<Vec<u8>>,
-    pub amp_eg_attack: Option<Vec<u8>>,
-    pub amp_eg_decay: Option<Vec<u8>>,
-    pub pitch_eg_int: Option<Vec<u8>>,
-    pub pitch_eg_attack: Option<Vec<u8>>,
-    pub pitch_eg_decay: Option<Vec<u8>>,
-    pub start_point: Option<Vec<u8>>,
-    pub length: Option<Vec<u8>>,
-    pub hi_cut: Option<Vec<u8>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub level_start: Option<MotionSequenceDef>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub level_end: Option<MotionSequenceDef>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pan_start: Option<MotionSequenceDef>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pan_end: Option<MotionSequenceDef>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub speed_start: Option<MotionSequenceDef>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub speed_end: Option<MotionSequenceDef>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub amp_eg_attack: Option<MotionSequenceDef>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub amp_eg_decay: Option<MotionSequenceDef>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pitch_eg_int: Option<MotionSequenceDef>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pitch_eg_attack: Option<MotionSequenceDef>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pitch_eg_decay: Option<MotionSequenceDef>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub start_point: Option<MotionSequenceDef>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub length: Option<MotionSequenceDef>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hi_cut: Option<MotionSequenceDef>,
+}
+
+// Key of a sample, pattern or part map, which is a string in formats like toml that only have
+// string keys
+#[derive(PartialEq, Eq, Hash)]
+struct SlotKey(u32);
+
+impl<'de> Deserialize<'de> for SlotKey {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct SlotKeyVisitor;
+
+        impl<'de> de::Visitor<'de> for SlotKeyVisitor {
+            type Value = SlotKey;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("a slot number")
+            }
+
+            fn visit_u64<E: de::Error>(self, value: u64) -> Result<SlotKey, E> {
+                value
+                    .try_into()
+                    .map(SlotKey)
+                    .map_err(|_| E::invalid_value(de::Unexpected::Unsigned(value), &self))
+            }
+
+            fn visit_i64<E: de::Error>(self, value: i64) -> Result<SlotKey, E> {
+                value
+                    .try_into()
+                    .map(SlotKey)
+                    .map_err(|_| E::invalid_value(de::Unexpected::Signed(value), &self))
+            }
+
+            fn visit_str<E: de::Error>(self, value: &str) -> Result<SlotKey, E> {
+                value
+                    .parse()
+                    .map(SlotKey)
+                    .map_err(|_| E::invalid_value(de::Unexpected::Str(value), &self))
+            }
+        }
+
+        deserializer.deserialize_any(SlotKeyVisitor)
+    }
+}
+
+fn deserialize_slot_map<'de, D, V>(deserializer: D) -> Result<HashMap<u32, V>, D::Error>
+where
+    D: Deserializer<'de>,
+    V: Deserialize<'de>,
+{
+    let map = HashMap::<SlotKey, V>::deserialize(deserializer)?;
+    Ok(map.into_iter().map(|(key, value)| (key.0, value)).collect())
+}
+
+fn deserialize_optional_slot_map<'de, D, V>(
+    deserializer: D,
+) -> Result<Option<HashMap<u32, V>>, D::Error>
+where
+    D: Deserializer<'de>,
+    V: Deserialize<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(transparent, bound = "V: Deserialize<'de>")]
+    struct SlotMap<V>(#[serde(deserialize_with = "deserialize_slot_map")] HashMap<u32, V>);
+
+    let map = Option::<SlotMap<V>>::deserialize(deserializer)?;
+    Ok(map.map(|map| map.0))
+}
+
+// Slot maps are written in slot order, formats that only have string keys like toml get the
+// slots as strings
+fn serialize_slot_map<S, V>(map: &HashMap<u32, V>, serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+    V: Serialize,
+{
+    let mut slots: Vec<_> = map.iter().collect();
+    slots.sort_by_key(|(slot, _)| **slot);
+    let mut map_serializer = serializer.serialize_map(Some(slots.len()))?;
+    for (slot, value) in slots {
+        if map_serializer.serialize_key(slot).is_err() {
+            map_serializer.serialize_key(&slot.to_string())?;
+        }
+        map_serializer.serialize_value(value)?;
+    }
+    map_serializer.end()
+}
+
+struct SlotMap<'a, V>(&'a HashMap<u32, V>);
+
+impl<V: Serialize> Serialize for SlotMap<'_, V> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serialize_slot_map(self.0, serializer)
+    }
+}
+
+fn serialize_optional_slot_map<S, V>(
+    map: &Option<HashMap<u32, V>>,
+    serializer: S,
+) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+    V: Serialize,
+{
+    map.as_ref().map(SlotMap).serialize(serializer)
+}
+
+fn serialize_optional_sorted_map<S, K, V>(
+    map: &Option<HashMap<K, V>>,
+    serializer: S,
+) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+    K: Ord + Serialize,
+    V: Serialize,
+{
+    map.as_ref()
+        .map(|map| map.iter().collect::<BTreeMap<_, _>>())
+        .serialize(serializer)
 }
 
 #[derive(Debug, PartialEq, Serialize, Deserialize)]
 #[serde(deny_unknown_fields)]
 pub struct PatternDef {
+    #[serde(
+        serialize_with = "serialize_slot_map",
+        deserialize_with = "deserialize_slot_map"
+    )]
     pub parts: HashMap<u32, PartDef>,
 }
 
-// TODO validation
-#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
 #[serde(deny_unknown_fields)]
 pub struct VolcaSample {
+    // Other configuration files to merge into this one, relative to this file
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub include: Option<Vec<String>>,
     // Default compression to apply for all
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub default_compression: Option<u32>,
+    // Default sampling rate to resample all samples to
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub default_resample: Option<u32>,
+    // Default silence threshold and normalization peak in dBFS for all samples
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub default_trim_silence: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub default_normalize: Option<f64>,
+    // Automatically lower the compression of samples so they fit in sample memory
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fit_memory: Option<bool>,
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "serialize_optional_slot_map",
+        deserialize_with = "deserialize_optional_slot_map"
+    )]
     pub samples: Option<HashMap<u32, SampleAction>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sample_dirs: Option<Vec<SampleDirDef>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub default_part_reverb: Option<ToggleDef>,
+    // Parts that pattern parts can take the fields they leave out from, by name
+    #[serde(
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "serialize_optional_sorted_map"
+    )]
+    pub part_templates: Option<HashMap<String, PartDef>>,
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "serialize_optional_slot_map",
+        deserialize_with = "deserialize_optional_slot_map"
+    )]
     pub patterns: Option<HashMap<u32, PatternDef>>,
 }
 
-trait VecU32Ext {
-    fn into_steps(&self) -> anyhow::Result<pattern::Steps>;
+impl VolcaSample {
+    /// Merges an included configuration into this one. Settings already set here take precedence,
+    /// a sample slot, part template or pattern defined in both is an error.
+    pub fn merge(&mut self, other: VolcaSample) -> anyhow::Result<()> {
+        self.default_compression = self.default_compression.or(other.default_compression);
+        self.default_resample = self.default_resample.or(other.default_resample);
+        self.default_trim_silence = self.default_trim_silence.or(other.default_trim_silence);
+        self.default_normalize = self.default_normalize.or(other.default_normalize);
+        self.fit_memory = self.fit_memory.or(other.fit_memory);
+        self.default_part_reverb = self.default_part_reverb.or(other.default_part_reverb);
+        merge_map("samples", &mut self.samples, other.samples)?;
+        merge_map(
+            "part_templates",
+            &mut self.part_templates,
+            other.part_templates,
+        )?;
+        merge_map("patterns", &mut self.patterns, other.patterns)?;
+        if let Some(sample_dirs) = other.sample_dirs {
+            self.sample_dirs
+                .get_or_insert_with(Vec::new)
+                .extend(sample_dirs);
+        }
+        Ok(())
+    }
+
+    /// Number of pattern parts using each sample
+    pub fn sample_references(&self) -> HashMap<u32, usize> {
+        let mut references = HashMap::new();
+        for pattern in self.patterns.iter().flat_map(|p| p.values()) {
+            for part in pattern.parts.values() {
+                if let Some(SampleRefDef::Slot(slot)) = part.sample {
+                    *references.entry(slot).or_insert(0) += 1;
+                }
+            }
+        }
+        references
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum ConfigFormat {
+    Ron,
+    Json,
+    Yaml,
+    Toml,
+}
+
+impl ConfigFormat {
+    pub const NAMES: [&'static str; 4] = ["ron", "json", "yaml", "toml"];
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "ron" => Some(ConfigFormat::Ron),
+            "json" => Some(ConfigFormat::Json),
+            "yaml" | "yml" => Some(ConfigFormat::Yaml),
+            "toml" => Some(ConfigFormat::Toml),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            ConfigFormat::Ron => "ron",
+            ConfigFormat::Json => "json",
+            ConfigFormat::Yaml => "yaml",
+            ConfigFormat::Toml => "toml",
+        }
+    }
+
+    /// Line comment marker, json has no comments
+    pub fn comment_prefix(self) -> Option<&'static str> {
+        match self {
+            ConfigFormat::Ron => Some("//"),
+            ConfigFormat::Json => None,
+            ConfigFormat::Yaml | ConfigFormat::Toml => Some("#"),
+        }
+    }
+
+    /// Guesses the format from the first line of the content that isn't blank or a comment
+    pub fn detect(content: &str) -> Option<Self> {
+        let line = content.lines().map(str::trim).find(|line| {
+            let is_comment =
+                line.starts_with("//") || (line.starts_with('#') && !line.starts_with("#!["));
+            !line.is_empty() && !is_comment
+        })?;
+        if line.starts_with("#![") || line.starts_with("VolcaSample") || line.starts_with('(') {
+            return Some(ConfigFormat::Ron);
+        }
+        if line.starts_with('{') {
+            return Some(ConfigFormat::Json);
+        }
+        if line.starts_with("---") {
+            return Some(ConfigFormat::Yaml);
+        }
+        if line.starts_with('[') {
+            return Some(ConfigFormat::Toml);
+        }
+        // a top level key, followed by "=" in toml or ":" in yaml
+        let key = line.trim_start_matches(|c: char| c.is_alphanumeric() || "_-\"".contains(c));
+        match key.trim_start().chars().next() {
+            Some('=') => Some(ConfigFormat::Toml),
+            Some(':') => Some(ConfigFormat::Yaml),
+            _ => None,
+        }
+    }
+
+    pub fn serialize(self, volca_sample: &VolcaSample) -> anyhow::Result<String> {
+        Ok(match self {
+            ConfigFormat::Ron => {
+                let config = ron::ser::PrettyConfig::new()
+                    .with_extensions(ron::extensions::Extensions::IMPLICIT_SOME);
+                ron::ser::to_string_pretty(volca_sample, config)?
+            }
+            ConfigFormat::Json => serde_json::to_string_pretty(volca_sample)?,
+            ConfigFormat::Yaml => serde_yaml::to_string(volca_sample)?,
+            ConfigFormat::Toml => toml::to_string(volca_sample)?,
+        })
+    }
+
+    pub fn deserialize(self, content: &str) -> anyhow::Result<VolcaSample> {
+        Ok(match self {
+            ConfigFormat::Ron => ron::de::from_str(content)?,
+            ConfigFormat::Json => serde_json::from_str(content)?,
+            ConfigFormat::Yaml => serde_yaml::from_str(content)?,
+            ConfigFormat::Toml => toml::from_str(content)?,
+        })
+    }
+}
+
+fn merge_map<K, V>(
+    name: &str,
+    map: &mut Option<HashMap<K, V>>,
+    other: Option<HashMap<K, V>>,
+) -> anyhow::Result<()>
+where
+    K: Eq + Hash + Ord + fmt::Display,
+{
+    let other = match other {
+        Some(other) => other,
+        None => return Ok(()),
+    };
+    let map = map.get_or_insert_with(HashMap::new);
+    let mut conflicts: Vec<_> = other.keys().filter(|k| map.contains_key(k)).collect();
+    if !conflicts.is_empty() {
+        conflicts.sort();
+        let conflicts: Vec<String> = conflicts
+            .iter()
+            .map(|k| format!("{}.{}", name, k))
+            .collect();
+        return Err(anyhow::anyhow!(
+            "{} defined more than once",
+            conflicts.join(", ")
+        ));
+    }
+    map.extend(other);
+    Ok(())
+}
+
+impl PartDef {
+    /// Fills in the fields left out of this part from a template. Steps and hits count as one
+    /// field, and motion sequences are filled in lane by lane.
+    pub fn inherit(&mut self, template: &PartDef) {
+        inherit(&mut self.template, &template.template);
+        inherit(&mut self.sample, &template.sample);
+        if self.steps.is_none() && self.hits.is_none() {
+            self.steps = template.steps.clone();
+            self.hits = template.hits.clone();
+        }
+        inherit(&mut self.motion, &template.motion);
+        inherit(&mut self.looped, &template.looped);
+        inherit(&mut self.reverb, &template.reverb);
+        inherit(&mut self.reverse, &template.reverse);
+        inherit(&mut self.mute, &template.mute);
+        inherit(&mut self.level, &template.level);
+        inherit(&mut self.pan, &template.pan);
+        inherit(&mut self.speed, &template.speed);
+        inherit(&mut self.amp_eg_attack, &template.amp_eg_attack);
+        inherit(&mut self.amp_eg_decay, &template.amp_eg_decay);
+        inherit(&mut self.pitch_eg_attack, &template.pitch_eg_attack);
+        inherit(&mut self.pitch_eg_int, &template.pitch_eg_int);
+        inherit(&mut self.pitch_eg_decay, &template.pitch_eg_decay);
+        inherit(&mut self.starting_point, &template.starting_point);
+        inherit(&mut self.length, &template.length);
+        inherit(&mut self.hi_cut, &template.hi_cut);
+        match (&mut self.motion_sequences, &template.motion_sequences) {
+            (Some(own), Some(template)) => own.inherit(template),
+            (None, Some(template)) => self.motion_sequences = Some(template.clone()),
+            _ => {}
+        }
+    }
+
+    /// Whether each step is on, from either steps or hits
+    pub fn step_states(&self) -> anyhow::Result<[bool; STEP_COUNT]> {
+        match (&self.steps, &self.hits) {
+            (Some(steps), None) => steps.states(),
+            (None, Some(hits)) => hits_to_states(hits),
+            (Some(_), Some(_)) => Err(anyhow::anyhow!("steps and hits can't both be given")),
+            (None, None) => Err(anyhow::anyhow!("either steps or hits is required")),
+        }
+    }
+}
+
+trait StepStatesExt {
+    fn into_steps(self) -> anyhow::Result<pattern::Steps>;
 }
 
-impl VecU32Ext for Vec<u32> {
-    fn into_steps(&self) -> anyhow::Result<pattern::Steps> {
+impl StepStatesExt for [bool; STEP_COUNT] {
+    fn into_steps(self) -> anyhow::Result<pattern::Steps> {
         let mut steps = pattern::Steps::builder();
-        for (index, value) in self.iter().enumerate() {
+        for (index, on) in self.iter().enumerate() {
             let step = pattern::Step::try_from_primitive(index as u8)?;
-            if *value == 1 {
+            if *on {
                 steps.on(step);
             }
         }
@@ -117,14 +1260,12 @@ impl VecU32Ext for Vec<u32> {
 }
 
 trait VecU8Ext {
-    fn into_motion_seq(&self) -> anyhow::Result<[u8; 16]>;
+    fn into_motion_seq(self) -> anyhow::Result<[u8; 16]>;
 }
 
-impl VecU8Ext for Vec<u8> {
-    fn into_motion_seq(&self) -> anyhow::Result<[u8; 16]> {
-        self.clone()
-            .as_slice()
-            .try_into()
+impl VecU8Ext for &[u8] {
+    fn into_motion_seq(self) -> anyhow::Result<[u8; 16]> {
+        self.try_into()
             .with_context(|| "unable to parse into motion sequence")
     }
 }
@@ -133,8 +1274,18 @@ pub fn parse_part_definition(
     part_definition: &PartDef,
     default_reverb: ToggleDef,
 ) -> anyhow::Result<pattern::Part> {
-    let mut part = pattern::Part::for_sample(part_definition.sample as u16)?;
-    let steps = part_definition.steps.into_steps()?;
+    if let Some(template) = &part_definition.template {
+        return Err(anyhow::anyhow!("Unapplied part template '{}'", template));
+    }
+    let sample = match &part_definition.sample {
+        Some(SampleRefDef::Slot(slot)) => *slot,
+        Some(SampleRefDef::Name(name)) => {
+            return Err(anyhow::anyhow!("Unresolved sample name '{}'", name));
+        }
+        None => return Err(anyhow::anyhow!("Part has no sample")),
+    };
+    let mut part = pattern::Part::for_sample(sample as u16)?;
+    let steps = part_definition.step_states()?.into_steps()?;
     part.with_steps(steps);
     if let Some(motion) = part_definition.motion {
         part.motion(motion.into());
@@ -156,11 +1307,11 @@ pub fn parse_part_definition(
     if let Some(level) = part_definition.level {
         part.level(level)?;
     }
-    if let Some(pan) = part_definition.pan {
-        part.pan(pan)?;
+    if let Some(pan) = &part_definition.pan {
+        part.pan(pan.value()?)?;
     }
-    if let Some(speed) = part_definition.speed {
-        part.speed(speed)?;
+    if let Some(speed) = &part_definition.speed {
+        part.speed(speed.value()?)?;
     }
     if let Some(amp_eg_attack) = part_definition.amp_eg_attack {
         part.amp_eg_attack(amp_eg_attack)?;
@@ -171,8 +1322,8 @@ pub fn parse_part_definition(
     if let Some(pitch_eg_attack) = part_definition.pitch_eg_attack {
         part.pitch_eg_attack(pitch_eg_attack)?;
     }
-    if let Some(pitch_eg_int) = part_definition.pitch_eg_int {
-        part.pitch_eg_int(pitch_eg_int)?;
+    if let Some(pitch_eg_int) = &part_definition.pitch_eg_int {
+        part.pitch_eg_int(pitch_eg_int.value()?)?;
     }
     if let Some(pitch_eg_decay) = part_definition.pitch_eg_decay {
         part.pitch_eg_decay(pitch_eg_decay)?;
@@ -188,46 +1339,46 @@ pub fn parse_part_definition(
     }
     if let Some(motion_seqs) = &part_definition.motion_sequences {
         if let Some(level_start) = motion_seqs.level_start.as_ref() {
-            part.level_start_motion_seq(level_start.into_motion_seq()?)?;
+            part.level_start_motion_seq(level_start.expand(Some(&PARAMETER))?)?;
         }
         if let Some(level_end) = motion_seqs.level_end.as_ref() {
-            part.level_end_motion_seq(level_end.into_motion_seq()?)?;
+            part.level_end_motion_seq(level_end.expand(Some(&PARAMETER))?)?;
         }
         if let Some(pan_start) = motion_seqs.pan_start.as_ref() {
-            part.pan_start_motion_seq(pan_start.into_motion_seq()?)?;
+            part.pan_start_motion_seq(pan_start.expand(Some(&CENTERED_PARAMETER))?)?;
         }
         if let Some(pan_end) = motion_seqs.pan_end.as_ref() {
-            part.pan_end_motion_seq(pan_end.into_motion_seq()?)?;
+            part.pan_end_motion_seq(pan_end.expand(Some(&CENTERED_PARAMETER))?)?;
         }
         if let Some(speed_start) = motion_seqs.speed_start.as_ref() {
-            part.speed_start_motion_seq(speed_start.into_motion_seq()?)?;
+            part.speed_start_motion_seq(speed_start.expand(None)?)?;
         }
         if let Some(speed_end) = motion_seqs.speed_end.as_ref() {
-            part.speed_end_motion_seq(speed_end.into_motion_seq()?)?;
+            part.speed_end_motion_seq(speed_end.expand(None)?)?;
         }
         if let Some(amp_eg_attack) = motion_seqs.amp_eg_attack.as_ref() {
-            part.amp_eg_attack_motion_seq(amp_eg_attack.into_motion_seq()?)?;
+            part.amp_eg_attack_motion_seq(amp_eg_attack.expand(Some(&PARAMETER))?)?;
         }
         if let Some(amp_eg_decay) = motion_seqs.amp_eg_decay.as_ref() {
-            part.amp_eg_decay_motion_seq(amp_eg_decay.into_motion_seq()?)?;
+            part.amp_eg_decay_motion_seq(amp_eg_decay.expand(Some(&PARAMETER))?)?;
         }
         if let Some(pitch_eg_int) = motion_seqs.pitch_eg_int.as_ref() {
-            part.pitch_eg_int_motion_seq(pitch_eg_int.into_motion_seq()?)?;
+            part.pitch_eg_int_motion_seq(pitch_eg_int.expand(Some(&CENTERED_PARAMETER))?)?;
         }
         if let Some(pitch_eg_attack) = motion_seqs.pitch_eg_attack.as_ref() {
-            part.pitch_eg_attack_motion_seq(pitch_eg_attack.into_motion_seq()?)?;
+            part.pitch_eg_attack_motion_seq(pitch_eg_attack.expand(Some(&PARAMETER))?)?;
         }
         if let Some(pitch_eg_decay) = motion_seqs.pitch_eg_decay.as_ref() {
-            part.pitch_eg_decay_motion_seq(pitch_eg_decay.into_motion_seq()?)?;
+            part.pitch_eg_decay_motion_seq(pitch_eg_decay.expand(Some(&PARAMETER))?)?;
         }
         if let Some(start_point) = motion_seqs.start_point.as_ref() {
-            part.start_point_motion_seq(start_point.into_motion_seq()?)?;
+            part.start_point_motion_seq(start_point.expand(Some(&PARAMETER))?)?;
         }
         if let Some(length) = motion_seqs.length.as_ref() {
-            part.length_motion_seq(length.into_motion_seq()?)?;
+            part.length_motion_seq(length.expand(Some(&PARAMETER))?)?;
         }
         if let Some(hi_cut) = motion_seqs.hi_cut.as_ref() {
-            part.hi_cut_motion_seq(hi_cut.into_motion_seq()?)?;
+            part.hi_cut_motion_seq(hi_cut.expand(Some(&PARAMETER))?)?;
         }
     }
     debug!("{:?}", part);
@@ -242,7 +1393,9 @@ pub fn parse_pattern_definition(
     let mut pattern = pattern::Pattern::default();
     for (part_index, part_definition) in pattern_definition.parts.iter() {
         debug!("Part Definition {}: {:?}", part_index, part_definition);
-        let part = parse_part_definition(part_definition, default_reverb)?;
+        let part = parse_part_definition(part_definition, default_reverb).with_context(|| {
+            format!("Cannot parse pattern {} part {}", pattern_index, part_index)
+        })?;
         pattern.with_part(*part_index as u8, part)?;
     }
     trace!("Pattern {}: {:?}", pattern_index, pattern);
@@ -252,18 +1405,211 @@ pub fn parse_pattern_definition(
 #[cfg(test)]
 mod test {
     use super::*;
-    use anyhow;
     use ron::de::from_str;
 
     #[test]
     fn test_parse_steps_definition() -> anyhow::Result<()> {
-        let steps_def: Vec<u32> = vec![1, 1, 1, 0, 1, 0, 1, 0, 1, 0, 0, 0, 1, 0, 0, 0];
-        let steps = steps_def.into_steps()?;
-        println!("{:016b}", steps.to_bytes());
+        let steps_def = from_str::<StepsDef>("[1, 1, 1, 0, 1, 0, 1, 0, 1, 0, 0, 0, 1, 0, 0, 0]")?;
+        let steps = steps_def.states()?.into_steps()?;
         assert_eq!(steps.to_bytes(), 0b0001000101010111);
+
+        let toggles = from_str::<StepsDef>(
+            "[true, true, true, false, true, false, true, false, \
+              true, false, false, false, true, false, false, false]",
+        )?;
+        assert_eq!(toggles.states()?, steps_def.states()?);
+        let text = from_str::<StepsDef>(r#""xxx. x.x. x... x...""#)?;
+        assert_eq!(text.states()?, steps_def.states()?);
+        let text = from_str::<StepsDef>(r#""1110|1010|1000|1000""#)?;
+        assert_eq!(text.states()?, steps_def.states()?);
+        assert_eq!(
+            hits_to_states(&[0, 1, 2, 4, 6, 8, 12])?,
+            steps_def.states()?
+        );
+
+        for invalid in [
+            "[1, 0, 1]",
+            "[1, 0, 0, 0, 1, 0, 0, 0, 1, 0, 0, 0, 1, 0, 0, 0, 1]",
+            "[1, 0, 0, 0, 1, 0, 0, 0, 1, 0, 0, 0, 2, 0, 0, 0]",
+            r#""x...x...x...x...x""#,
+            r#""x...x...x...o...""#,
+        ] {
+            assert!(
+                from_str::<StepsDef>(invalid)?.states().is_err(),
+                "{}",
+                invalid
+            );
+        }
+        assert!(hits_to_states(&[16]).is_err());
         Ok(())
     }
 
+    #[test]
+    fn test_motion_sequence_generators() -> anyhow::Result<()> {
+        let expand = |generator: &str, range| {
+            MotionSequenceDef::Generator(String::from(generator)).expand(range)
+        };
+        assert_eq!(
+            expand("ramp(0, 150)", Some(&PARAMETER))?,
+            [0, 10, 20, 30, 40, 50, 60, 70, 80, 90, 100, 110, 120, 127, 127, 127]
+        );
+        assert_eq!(expand("hold(64)", Some(&PARAMETER))?, [64; 16]);
+
+        let triangle = expand("triangle", Some(&CENTERED_PARAMETER))?;
+        assert_eq!((triangle[0], triangle[4], triangle[8]), (1, 64, 127));
+        assert_eq!(
+            triangle,
+            expand("triangle(1, 127)", Some(&CENTERED_PARAMETER))?
+        );
+
+        let sine = expand("sine(0, 100, 1)", Some(&PARAMETER))?;
+        assert_eq!((sine[0], sine[4], sine[8], sine[12]), (50, 100, 50, 0));
+
+        let random = expand("random(10, 20, 7)", Some(&PARAMETER))?;
+        assert!(random.iter().all(|value| (10..=20).contains(value)));
+        assert_eq!(random, expand("random(10, 20, 7)", Some(&PARAMETER))?);
+        assert_ne!(random, expand("random(10, 20, 8)", Some(&PARAMETER))?);
+
+        // speeds aren't clamped
+        assert_eq!(expand("hold(200)", None)?, [200; 16]);
+        assert_eq!(expand("ramp(129, 255)", None)?[15], 255);
+        for invalid in ["hold(100)", "ramp(40, 100)", "ramp(129, 300)", "hold(-1)"] {
+            assert!(expand(invalid, None).is_err(), "{}", invalid);
+        }
+
+        for invalid in ["wobble(1)", "ramp(1)", "ramp(1, x)", "ramp(1, 2"] {
+            assert!(expand(invalid, Some(&PARAMETER)).is_err(), "{}", invalid);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_short_motion_sequences() -> anyhow::Result<()> {
+        let repeat = from_str::<MotionSequenceDef>("(values: [1, 2, 3, 4], mode: repeat)")?;
+        assert_eq!(
+            repeat.expand(Some(&PARAMETER))?,
+            [1, 2, 3, 4, 1, 2, 3, 4, 1, 2, 3, 4, 1, 2, 3, 4]
+        );
+        let interpolate = serde_json::from_str::<MotionSequenceDef>(
+            r#"{ "values": [0, 120, 60], "mode": "interpolate" }"#,
+        )?;
+        assert_eq!(
+            interpolate.expand(Some(&PARAMETER))?,
+            [0, 16, 32, 48, 64, 80, 96, 112, 116, 108, 100, 92, 84, 76, 68, 60]
+        );
+        let hold = from_str::<MotionSequenceDef>("(values: [64], mode: interpolate)")?;
+        assert_eq!(hold.expand(Some(&PARAMETER))?, [64; 16]);
+
+        let empty = from_str::<MotionSequenceDef>("(values: [], mode: repeat)")?;
+        assert!(empty.expand(Some(&PARAMETER)).is_err());
+        let values = vec![64; 17];
+        let long = MotionSequenceDef::Short(ShortSequenceDef {
+            values,
+            mode: SequenceModeDef::Interpolate,
+        });
+        assert!(long.expand(Some(&PARAMETER)).is_err());
+        assert!(from_str::<MotionSequenceDef>("(values: [1], mode: stretch)").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_motion_sequence_ranges() -> anyhow::Result<()> {
+        assert!(check_speed_modes(&[40, 64, 88, 100]).is_ok());
+        assert!(check_speed_modes(&[129, 192, 255]).is_ok());
+        let error = check_speed_modes(&[40, 192, 64, 200]).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "semitone and continuous speeds can't be mixed, the sequence is semitone (40-88) but [1], [3] are continuous (129-255)"
+        );
+        let error = check_speed_modes(&[40, 192, 200]).unwrap_err();
+        assert!(error
+            .to_string()
+            .contains("the sequence is continuous (129-255) but [0] is"));
+
+        let values = MotionSequenceDef::Values(vec![0; 16]);
+        assert!(values.expand(Some(&PARAMETER)).is_ok());
+        assert!(values.expand(Some(&CENTERED_PARAMETER)).is_err());
+        assert!(values.expand(None).is_err());
+        let short = from_str::<MotionSequenceDef>("(values: [40, 200], mode: interpolate)")?;
+        assert!(short.expand(None).is_err());
+        let short = from_str::<MotionSequenceDef>("(values: [129, 200], mode: interpolate)")?;
+        assert!(short.expand(None).is_ok());
+
+        let generator = |generator: &str| MotionSequenceDef::Generator(String::from(generator));
+        assert!(generator("ramp(40, 200)").expand(None).is_err());
+        assert!(generator("sine(130, 250, 200)").expand(None).is_ok());
+        assert!(generator("random(40, 88, 1000)").expand(None).is_ok());
+        Ok(())
+    }
+
+    #[test]
+    fn test_parameter_units() -> anyhow::Result<()> {
+        let pan = |pan: &str| from_str::<PanDef>(pan).unwrap().value();
+        assert_eq!(pan("100")?, 100);
+        assert_eq!(pan(r#""C""#)?, 64);
+        assert_eq!(pan(r#""L32""#)?, 32);
+        assert_eq!(pan(r#""r20""#)?, 84);
+        assert_eq!(pan(r#""L63""#)?, 1);
+        for invalid in [r#""L64""#, r#""R""#, r#""X1""#, r#""C1""#, r#""""#] {
+            assert!(pan(invalid).is_err(), "{}", invalid);
+        }
+
+        let speed = |speed: &str| from_str::<SpeedDef>(speed).unwrap().value();
+        assert_eq!(speed("192")?, 192);
+        assert_eq!(speed(r#""semitones(+3)""#)?, 67);
+        assert_eq!(speed(r#""semitones(-24)""#)?, 40);
+        assert_eq!(speed(r#""continuous(-0.25)""#)?, 176);
+        assert_eq!(speed(r#""continuous(1)""#)?, 255);
+        for invalid in [
+            r#""semitones(25)""#,
+            r#""semitones(1.5)""#,
+            r#""continuous(-1.5)""#,
+            r#""octaves(1)""#,
+        ] {
+            assert!(speed(invalid).is_err(), "{}", invalid);
+        }
+
+        let pitch_eg_int = |int: &str| from_str::<PitchEgIntDef>(int).unwrap().value();
+        assert_eq!(pitch_eg_int("100")?, 100);
+        assert_eq!(pitch_eg_int(r#""+12""#)?, 76);
+        assert_eq!(pitch_eg_int(r#""-12""#)?, 52);
+        assert_eq!(pitch_eg_int(r#""-63""#)?, 1);
+        assert!(pitch_eg_int(r#""-64""#).is_err());
+        assert!(pitch_eg_int(r#""12""#).is_err());
+        assert!(pitch_eg_int(r#""twelve""#).is_err());
+        assert_eq!(pitch_eg_int("-12")?, pitch_eg_int(r#""-12""#)?);
+        assert_eq!(pitch_eg_int("-63")?, 1);
+        assert!(pitch_eg_int("-64").is_err());
+        assert_eq!(pitch_eg_int("127")?, 127);
+        assert!(pitch_eg_int("0").is_err());
+        assert!(pitch_eg_int("128").is_err());
+        assert!(pitch_eg_int("200").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_detect_format() {
+        let detect = ConfigFormat::detect;
+        assert_eq!(
+            detect("#![enable(implicit_some)]\nVolcaSample()"),
+            Some(ConfigFormat::Ron)
+        );
+        assert_eq!(detect("// kit\nVolcaSample(\n)"), Some(ConfigFormat::Ron));
+        assert_eq!(detect("\n  {\"samples\": {}}"), Some(ConfigFormat::Json));
+        assert_eq!(detect("---\nsamples:"), Some(ConfigFormat::Yaml));
+        assert_eq!(
+            detect("# kit\ndefault_compression: 8"),
+            Some(ConfigFormat::Yaml)
+        );
+        assert_eq!(
+            detect("# kit\ndefault_compression = 8"),
+            Some(ConfigFormat::Toml)
+        );
+        assert_eq!(detect("[samples.0.Sample]"), Some(ConfigFormat::Toml));
+        assert_eq!(detect("hello"), None);
+        assert_eq!(detect(""), None);
+    }
+
     #[test]
     fn test_sample() -> anyhow::Result<()> {
         let ron_data = r#"
@@ -272,17 +1618,37 @@ mod test {
             samples: {
                 0: Sample((
                     file: "kick.wav",
+                    name: "kick",
                 )),
                 1: Sample((
                     file: "kick.wav",
                     compression: 8,
+                    start: frames(100),
+                    end: milliseconds(250),
                 )),
                 2: Sample((
                     file: "kick.wav",
                     compression: 16,
+                    channels: side,
+                    resample: 31250,
+                    trim_silence: -60,
+                    normalize: -0.5,
                 )),
                 3: Erase,
-            }
+                4: Slices((
+                    file: "break.wav",
+                    count: 4,
+                )),
+                8: Slices((
+                    file: "break.wav",
+                    markers: [frames(1000), seconds(0.5)],
+                    compression: 8,
+                )),
+            },
+            sample_dirs: [
+                (files: "drums/*.wav", slot: 20, compression: 12),
+                (files: "fx/*.wav"),
+            ],
         )
         "#;
 
@@ -291,23 +1657,47 @@ mod test {
           "samples": {
             "0": {
               "Sample": {
-                "file": "kick.wav"
+                "file": "kick.wav",
+                "name": "kick"
               }
             },
             "1": {
               "Sample": {
                 "file": "kick.wav",
-                "compression": 8
+                "compression": 8,
+                "start": { "frames": 100 },
+                "end": { "milliseconds": 250 }
               }
             },
             "2": {
               "Sample": {
                 "file": "kick.wav",
-                "compression": 16
+                "compression": 16,
+                "channels": "side",
+                "resample": 31250,
+                "trim_silence": -60,
+                "normalize": -0.5
               }
             },
-            "3": "Erase"
-          }
+            "3": "Erase",
+            "4": {
+              "Slices": {
+                "file": "break.wav",
+                "count": 4
+              }
+            },
+            "8": {
+              "Slices": {
+                "file": "break.wav",
+                "markers": [ { "frames": 1000 }, { "seconds": 0.5 } ],
+                "compression": 8
+              }
+            }
+          },
+          "sample_dirs": [
+            { "files": "drums/*.wav", "slot": 20, "compression": 12 },
+            { "files": "fx/*.wav" }
+          ]
         }
         "#;
 
@@ -316,32 +1706,167 @@ mod test {
             0:
                 Sample:
                     file: kick.wav
+                    name: kick
             1:
                 Sample:
                     file: kick.wav
                     compression: 8
+                    start:
+                        frames: 100
+                    end:
+                        milliseconds: 250
             2:
                 Sample:
                     file: kick.wav
                     compression: 16
+                    channels: side
+                    resample: 31250
+                    trim_silence: -60
+                    normalize: -0.5
             3: Erase
+            4:
+                Slices:
+                    file: break.wav
+                    count: 4
+            8:
+                Slices:
+                    file: break.wav
+                    markers: [ frames: 1000, seconds: 0.5 ]
+                    compression: 8
+        sample_dirs:
+            - files: drums/*.wav
+              slot: 20
+              compression: 12
+            - files: fx/*.wav
+        "#;
+
+        let toml_data = r#"
+        [samples.0.Sample]
+        file = "kick.wav"
+        name = "kick"
+
+        [samples.1.Sample]
+        file = "kick.wav"
+        compression = 8
+        start = { frames = 100 }
+        end = { milliseconds = 250.0 }
+
+        [samples.2.Sample]
+        file = "kick.wav"
+        compression = 16
+        channels = "side"
+        resample = 31250
+        trim_silence = -60.0
+        normalize = -0.5
+
+        [samples]
+        3 = "Erase"
+
+        [samples.4.Slices]
+        file = "break.wav"
+        count = 4
+
+        [samples.8.Slices]
+        file = "break.wav"
+        markers = [ { frames = 1000 }, { seconds = 0.5 } ]
+        compression = 8
+
+        [[sample_dirs]]
+        files = "drums/*.wav"
+        slot = 20
+        compression = 12
+
+        [[sample_dirs]]
+        files = "fx/*.wav"
         "#;
 
         let parsed_ron = from_str::<VolcaSample>(ron_data)?;
         let parsed_json = serde_json::from_str::<VolcaSample>(json_data)?;
         let parsed_yaml = serde_yaml::from_str::<VolcaSample>(yaml_data)?;
+        let parsed_toml = toml::from_str::<VolcaSample>(toml_data)?;
 
         assert_eq!(parsed_ron, parsed_json);
         assert_eq!(parsed_ron, parsed_yaml);
+        assert_eq!(parsed_ron, parsed_toml);
+
+        for format in ConfigFormat::NAMES
+            .iter()
+            .filter_map(|n| ConfigFormat::from_name(n))
+        {
+            let serialized = format.serialize(&parsed_ron)?;
+            assert_eq!(format.deserialize(&serialized)?, parsed_ron);
+        }
 
         Ok(())
     }
 
+    #[test]
+    fn test_merge() -> anyhow::Result<()> {
+        let mut volca_sample = from_str::<VolcaSample>(
+            r#"
+            #![enable(implicit_some)]
+            VolcaSample(
+                include: ["drums.ron"],
+                default_compression: 16,
+                samples: { 0: Sample((file: "kick.wav")) },
+            )
+            "#,
+        )?;
+        let drums = from_str::<VolcaSample>(
+            r#"
+            #![enable(implicit_some)]
+            VolcaSample(
+                default_compression: 8,
+                default_resample: 31250,
+                samples: { 1: Sample((file: "snare.wav")) },
+                part_templates: { "soft": (level: 40) },
+                patterns: { 0: (parts: { 0: (sample: 1, steps: [1]) }) },
+            )
+            "#,
+        )?;
+        volca_sample.merge(drums)?;
+        assert_eq!(volca_sample.default_compression, Some(16));
+        assert_eq!(volca_sample.default_resample, Some(31250));
+        assert_eq!(volca_sample.samples.as_ref().unwrap().len(), 2);
+        assert_eq!(volca_sample.patterns.as_ref().unwrap().len(), 1);
+        assert_eq!(
+            volca_sample.part_templates.as_ref().unwrap()["soft"].level,
+            Some(40)
+        );
+
+        let conflicting = from_str::<VolcaSample>(
+            r#"VolcaSample(part_templates: Some({ "soft": (level: Some(20)) }))"#,
+        )?;
+        let error = volca_sample.merge(conflicting).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "part_templates.soft defined more than once"
+        );
+
+        let conflicting = from_str::<VolcaSample>(
+            r#"VolcaSample(samples: Some({ 1: Erase, 0: Erase, 2: Erase }))"#,
+        )?;
+        let error = volca_sample.merge(conflicting).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "samples.0, samples.1 defined more than once"
+        );
+        Ok(())
+    }
+
     #[test]
     fn test_pattern() -> anyhow::Result<()> {
         let ron_data = r#"
         #![enable(implicit_some)]
         VolcaSample(
+            part_templates: {
+                "ghost": (
+                    template: "soft",
+                    steps: "..x. ..x. ..x. ..x.",
+                    motion_sequences: (level_start: "hold(40)"),
+                ),
+                "soft": (level: 40, pan: "R10"),
+            },
             patterns: {
                 0: (
                     parts: {
@@ -396,6 +1921,21 @@ mod test {
                             length: 64,
                             hi_cut: 64,
                             steps: [1, 0, 1, 0, 1, 0, 1, 0, 1, 0, 1, 0, 1, 0, 1, 0],
+                        ),
+                        3: (
+                            sample: 3,
+                            steps: "x.x. x.x. x.x. x.x.",
+                            pan: "L32",
+                            speed: "semitones(+3)",
+                            pitch_eg_int: -12,
+                            motion_sequences: (
+                                level_end: "ramp(120, 1)",
+                                pan_end: (values: [1, 127], mode: interpolate),
+                            )
+                        ),
+                        4: (
+                            sample: 4,
+                            hits: [0, 2, 4, 6, 8, 10, 12, 14],
                         )
                     }
                 )
@@ -405,6 +1945,14 @@ mod test {
 
         let json_data = r#"
         {
+          "part_templates": {
+            "ghost": {
+              "template": "soft",
+              "steps": "..x. ..x. ..x. ..x.",
+              "motion_sequences": { "level_start": "hold(40)" }
+            },
+            "soft": { "level": 40, "pan": "R10" }
+          },
           "patterns": {
             "0": {
               "parts": {
@@ -459,6 +2007,21 @@ mod test {
                   "length": 64,
                   "hi_cut": 64,
                   "steps": [ 1, 0, 1, 0, 1, 0, 1, 0, 1, 0, 1, 0, 1, 0, 1, 0 ]
+                },
+                "3": {
+                  "sample": 3,
+                  "steps": "x.x. x.x. x.x. x.x.",
+                  "pan": "L32",
+                  "speed": "semitones(+3)",
+                  "pitch_eg_int": -12,
+                  "motion_sequences": {
+                    "level_end": "ramp(120, 1)",
+                    "pan_end": { "values": [ 1, 127 ], "mode": "interpolate" }
+                  }
+                },
+                "4": {
+                  "sample": 4,
+                  "hits": [ 0, 2, 4, 6, 8, 10, 12, 14 ]
                 }
               }
             }
@@ -467,6 +2030,15 @@ mod test {
         "#;
 
         let yaml_data = r#"
+        part_templates:
+            ghost:
+                template: soft
+                steps: ..x. ..x. ..x. ..x.
+                motion_sequences:
+                    level_start: hold(40)
+            soft:
+                level: 40
+                pan: R10
         patterns:
             0:
                 parts:
@@ -517,14 +2089,109 @@ mod test {
                         length: 64
                         hi_cut: 64
                         steps: [ 1, 0, 1, 0, 1, 0, 1, 0, 1, 0, 1, 0, 1, 0, 1, 0 ]
+                    3:
+                        sample: 3
+                        steps: "x.x. x.x. x.x. x.x."
+                        pan: L32
+                        speed: semitones(+3)
+                        pitch_eg_int: -12
+                        motion_sequences:
+                            level_end: ramp(120, 1)
+                            pan_end: { values: [ 1, 127 ], mode: interpolate }
+                    4:
+                        sample: 4
+                        hits: [ 0, 2, 4, 6, 8, 10, 12, 14 ]
+        "#;
+
+        let toml_data = r#"
+        [part_templates.ghost]
+        template = "soft"
+        steps = "..x. ..x. ..x. ..x."
+        motion_sequences = { level_start = "hold(40)" }
+
+        [part_templates.soft]
+        level = 40
+        pan = "R10"
+
+        [patterns.0.parts.0]
+        sample = 0
+        steps = [ 0, 1, 0, 1, 0, 1, 0, 1, 0, 1, 0, 1, 0, 1, 0, 1 ]
+        motion = "off"
+        loop = "on"
+        reverb = "off"
+        reverse = "on"
+        mute = "off"
+
+        [patterns.0.parts.0.motion_sequences]
+        level_start = [ 1, 8, 16, 24, 32, 40, 48, 56, 64, 72, 80, 88, 96, 104, 112, 120 ]
+        level_end = [ 1, 8, 16, 24, 32, 40, 48, 56, 64, 72, 80, 88, 96, 104, 112, 120 ]
+        pan_start = [ 1, 8, 16, 24, 32, 40, 48, 56, 64, 72, 80, 88, 96, 104, 112, 120 ]
+        pan_end = [ 1, 8, 16, 24, 32, 40, 48, 56, 64, 72, 80, 88, 96, 104, 112, 120 ]
+        speed_start = [ 40, 43, 46, 49, 52, 55, 58, 61, 64, 67, 70, 73, 76, 79, 82, 85 ]
+        speed_end = [ 129, 137, 145, 153, 161, 169, 177, 185, 193, 201, 209, 217, 225, 233, 241, 249 ]
+        amp_eg_attack = [ 1, 8, 16, 24, 32, 40, 48, 56, 64, 72, 80, 88, 96, 104, 112, 120 ]
+        amp_eg_decay = [ 1, 8, 16, 24, 32, 40, 48, 56, 64, 72, 80, 88, 96, 104, 112, 120 ]
+        pitch_eg_int = [ 1, 8, 16, 24, 32, 40, 48, 56, 64, 72, 80, 88, 96, 104, 112, 120 ]
+        pitch_eg_attack = [ 1, 8, 16, 24, 32, 40, 48, 56, 64, 72, 80, 88, 96, 104, 112, 120 ]
+        pitch_eg_decay = [ 1, 8, 16, 24, 32, 40, 48, 56, 64, 72, 80, 88, 96, 104, 112, 120 ]
+        start_point = [ 1, 8, 16, 24, 32, 40, 48, 56, 64, 72, 80, 88, 96, 104, 112, 120 ]
+        length = [ 1, 8, 16, 24, 32, 40, 48, 56, 64, 72, 80, 88, 96, 104, 112, 120 ]
+        hi_cut = [ 1, 8, 16, 24, 32, 40, 48, 56, 64, 72, 80, 88, 96, 104, 112, 120 ]
+
+        [patterns.0.parts.1]
+        sample = 1
+        steps = [ 1, 0, 1, 0, 1, 0, 1, 0, 1, 0, 1, 0, 1, 0, 1, 0 ]
+        motion = "on"
+        loop = "off"
+        reverb = "on"
+        reverse = "off"
+        mute = "on"
+        motion_sequences = { level_start = [ 1, 8, 16, 24, 32, 40, 48, 56, 64, 72, 80, 88, 96, 104, 112, 120 ] }
+
+        [patterns.0.parts.2]
+        sample = 2
+        level = 64
+        pan = 127
+        speed = 192
+        amp_eg_attack = 64
+        amp_eg_decay = 64
+        pitch_eg_attack = 64
+        pitch_eg_int = 64
+        pitch_eg_decay = 64
+        starting_point = 64
+        length = 64
+        hi_cut = 64
+        steps = [ 1, 0, 1, 0, 1, 0, 1, 0, 1, 0, 1, 0, 1, 0, 1, 0 ]
+
+        [patterns.0.parts.3]
+        sample = 3
+        steps = "x.x. x.x. x.x. x.x."
+        pan = "L32"
+        speed = "semitones(+3)"
+        pitch_eg_int = -12
+        motion_sequences = { level_end = "ramp(120, 1)", pan_end = { values = [ 1, 127 ], mode = "interpolate" } }
+
+        [patterns.0.parts.4]
+        sample = 4
+        hits = [ 0, 2, 4, 6, 8, 10, 12, 14 ]
         "#;
 
         let parsed_ron = from_str::<VolcaSample>(ron_data)?;
         let parsed_json = serde_json::from_str::<VolcaSample>(json_data)?;
         let parsed_yaml = serde_yaml::from_str::<VolcaSample>(yaml_data)?;
+        let parsed_toml = toml::from_str::<VolcaSample>(toml_data)?;
 
         assert_eq!(parsed_ron, parsed_json);
         assert_eq!(parsed_ron, parsed_yaml);
+        assert_eq!(parsed_ron, parsed_toml);
+
+        for format in ConfigFormat::NAMES
+            .iter()
+            .filter_map(|n| ConfigFormat::from_name(n))
+        {
+            let serialized = format.serialize(&parsed_ron)?;
+            assert_eq!(format.deserialize(&serialized)?, parsed_ron);
+        }
 
         let patterns: Vec<pattern::Pattern> = parsed_ron
             .patterns
@@ -532,10 +2199,7 @@ mod test {
             .iter()
             .map(|(i, pd)| parse_pattern_definition(*i, pd, ToggleDef::Off).unwrap())
             .collect();
-
-        println!("{:?}", patterns);
-
-        // println!("{:?}", parsed);
+        assert_eq!(patterns.len(), 1);
         Ok(())
     }
 }
@@ -0,0 +1,1111 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::ops::{Range, RangeInclusive};
+
+use crate::parse::*;
+
+#[derive(Clone, Debug, PartialEq)]
+enum Segment {
+    Key(String),
+    Index(usize),
+}
+
+// Path to a field in the configuration, e.g. patterns.3.parts.7.motion_sequences.pan_start[4]
+#[derive(Clone, Debug, Default, PartialEq)]
+struct FieldPath(Vec<Segment>);
+
+impl FieldPath {
+    fn key<K: ToString>(&self, key: K) -> Self {
+        let mut path = self.clone();
+        path.0.push(Segment::Key(key.to_string()));
+        path
+    }
+
+    fn index(&self, index: usize) -> Self {
+        let mut path = self.clone();
+        path.0.push(Segment::Index(index));
+        path
+    }
+}
+
+impl fmt::Display for FieldPath {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, segment) in self.0.iter().enumerate() {
+            match segment {
+                Segment::Key(key) if i == 0 => write!(f, "{}", key)?,
+                Segment::Key(key) => write!(f, ".{}", key)?,
+                Segment::Index(index) => write!(f, "[{}]", index)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ValidationError {
+    pub path: String,
+    pub message: String,
+    // Line and column in the configuration file, both starting at 1
+    pub location: Option<(usize, usize)>,
+}
+
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.path, self.message)?;
+        if let Some((line, column)) = self.location {
+            write!(f, " (line {}, column {})", line, column)?;
+        }
+        Ok(())
+    }
+}
+
+// Every problem found in a configuration file, as the error that stops reading it
+#[derive(Debug)]
+pub struct ValidationErrors {
+    pub file_name: String,
+    pub errors: Vec<ValidationError>,
+}
+
+impl fmt::Display for ValidationErrors {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Invalid configuration in file '{}':", self.file_name)?;
+        for error in self.errors.iter() {
+            write!(f, "\n{}", error)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ValidationErrors {}
+
+const SAMPLE_SLOTS: RangeInclusive<u32> = 0..=99;
+const PATTERN_SLOTS: RangeInclusive<u32> = 0..=9;
+const PART_SLOTS: RangeInclusive<u32> = 0..=9;
+const COMPRESSION: RangeInclusive<u32> = 8..=16;
+
+struct Validator<'a> {
+    source: &'a str,
+    errors: Vec<ValidationError>,
+}
+
+impl<'a> Validator<'a> {
+    fn error(&mut self, path: &FieldPath, message: String) {
+        self.errors.push(ValidationError {
+            path: path.to_string(),
+            message,
+            location: locate(self.source, path),
+        });
+    }
+
+    fn check_range<T>(&mut self, path: &FieldPath, value: T, range: &RangeInclusive<T>)
+    where
+        T: PartialOrd + fmt::Display,
+    {
+        if !range.contains(&value) {
+            self.error(
+                path,
+                format!(
+                    "value {} is out of range {}-{}",
+                    value,
+                    range.start(),
+                    range.end()
+                ),
+            );
+        }
+    }
+
+    fn check_speed(&mut self, path: &FieldPath, speed: u8) {
+        if !SPEED_SEMITONE.contains(&speed) && !SPEED_CONTINUOUS.contains(&speed) {
+            self.error(
+                path,
+                format!(
+                    "value {} is out of range, valid values are 40-88 (semitone) or 129-255 (continuous)",
+                    speed
+                ),
+            );
+        }
+    }
+
+    fn check_level(&mut self, path: &FieldPath, level: Option<f64>) {
+        if let Some(level) = level {
+            if level > 0.0 {
+                self.error(path, format!("value {} must be at most 0 dBFS", level));
+            }
+        }
+    }
+
+    fn check_offset(&mut self, path: &FieldPath, offset: Option<OffsetDef>) {
+        if let Some(OffsetDef::Seconds(value)) | Some(OffsetDef::Milliseconds(value)) = offset {
+            if value < 0.0 {
+                self.error(path, format!("value {} must be at least 0", value));
+            }
+        }
+    }
+
+    fn check_resample(&mut self, path: &FieldPath, resample: Option<u32>) {
+        if resample == Some(0) {
+            self.error(path, String::from("sampling rate must be greater than 0"));
+        }
+    }
+
+    fn check_compression(&mut self, path: &FieldPath, compression: Option<u32>) {
+        if let Some(compression) = compression {
+            self.check_range(path, compression, &COMPRESSION);
+        }
+    }
+
+    fn volca_sample(&mut self, volca_sample: &VolcaSample) {
+        let root = FieldPath::default();
+        self.check_compression(
+            &root.key("default_compression"),
+            volca_sample.default_compression,
+        );
+        self.check_resample(&root.key("default_resample"), volca_sample.default_resample);
+        self.check_level(
+            &root.key("default_trim_silence"),
+            volca_sample.default_trim_silence,
+        );
+        self.check_level(
+            &root.key("default_normalize"),
+            volca_sample.default_normalize,
+        );
+
+        if let Some(samples) = &volca_sample.samples {
+            self.samples(&root.key("samples"), samples);
+        }
+        if let Some(sample_dirs) = &volca_sample.sample_dirs {
+            let path = root.key("sample_dirs");
+            for (i, sample_dir) in sample_dirs.iter().enumerate() {
+                let path = path.index(i);
+                if let Some(slot) = sample_dir.slot {
+                    self.check_range(&path.key("slot"), slot, &SAMPLE_SLOTS);
+                }
+                self.check_compression(&path.key("compression"), sample_dir.compression);
+                self.check_resample(&path.key("resample"), sample_dir.resample);
+                self.check_level(&path.key("trim_silence"), sample_dir.trim_silence);
+                self.check_level(&path.key("normalize"), sample_dir.normalize);
+            }
+        }
+        if let Some(part_templates) = &volca_sample.part_templates {
+            let path = root.key("part_templates");
+            let mut part_templates: Vec<_> = part_templates.iter().collect();
+            part_templates.sort_by_key(|(name, _)| *name);
+            for (name, part_template) in part_templates {
+                self.part(&path.key(name), part_template, true);
+            }
+        }
+        if let Some(patterns) = &volca_sample.patterns {
+            let path = root.key("patterns");
+            for (index, pattern) in sorted(patterns) {
+                let path = path.key(index);
+                self.check_range(&path, index, &PATTERN_SLOTS);
+                self.pattern(&path, pattern);
+            }
+        }
+    }
+
+    fn samples(&mut self, path: &FieldPath, samples: &HashMap<u32, SampleAction>) {
+        let mut occupied = HashMap::new();
+        for (index, sample_action) in sorted(samples) {
+            let path = path.key(index);
+            let slot_count = match sample_action {
+                SampleAction::Sample(sample) => {
+                    self.check_compression(&path.key("compression"), sample.compression);
+                    self.check_resample(&path.key("resample"), sample.resample);
+                    self.check_level(&path.key("trim_silence"), sample.trim_silence);
+                    self.check_level(&path.key("normalize"), sample.normalize);
+                    self.check_offset(&path.key("start"), sample.start);
+                    self.check_offset(&path.key("end"), sample.end);
+                    1
+                }
+                SampleAction::Slices(slices) => {
+                    self.check_compression(&path.key("compression"), slices.compression);
+                    self.check_resample(&path.key("resample"), slices.resample);
+                    self.check_level(&path.key("trim_silence"), slices.trim_silence);
+                    self.check_level(&path.key("normalize"), slices.normalize);
+                    for (i, marker) in slices.markers.iter().flatten().enumerate() {
+                        self.check_offset(&path.key("markers").index(i), Some(*marker));
+                    }
+                    match slices.slot_count() {
+                        Ok(slot_count) => slot_count,
+                        Err(e) => {
+                            self.error(&path, e.to_string());
+                            1
+                        }
+                    }
+                }
+                SampleAction::Erase => 1,
+            };
+
+            // slices take up consecutive slots starting at their index
+            for slot in index..index.saturating_add(slot_count) {
+                if !SAMPLE_SLOTS.contains(&slot) {
+                    self.error(
+                        &path,
+                        format!(
+                            "sample slot {} is out of range {}-{}",
+                            slot,
+                            SAMPLE_SLOTS.start(),
+                            SAMPLE_SLOTS.end()
+                        ),
+                    );
+                    break;
+                }
+                if let Some(other) = occupied.insert(slot, index) {
+                    self.error(
+                        &path,
+                        format!("sample slot {} is already used by samples.{}", slot, other),
+                    );
+                }
+            }
+        }
+    }
+
+    fn pattern(&mut self, path: &FieldPath, pattern: &PatternDef) {
+        let path = path.key("parts");
+        for (index, part) in sorted(&pattern.parts) {
+            let path = path.key(index);
+            self.check_range(&path, index, &PART_SLOTS);
+            self.part(&path, part, false);
+        }
+    }
+
+    fn part(&mut self, path: &FieldPath, part: &PartDef, is_template: bool) {
+        // templates, and parts using one, can leave out the sample and steps, they are checked
+        // again once the templates are applied
+        let partial = is_template || part.template.is_some();
+        match part.sample {
+            Some(SampleRefDef::Slot(slot)) => {
+                self.check_range(&path.key("sample"), slot, &SAMPLE_SLOTS)
+            }
+            // names are checked when they are resolved
+            Some(SampleRefDef::Name(_)) => {}
+            None if partial => {}
+            None => self.error(path, String::from("sample is required")),
+        }
+        if !partial || part.steps.is_some() || part.hits.is_some() {
+            if let Err(error) = part.step_states() {
+                let path = match (&part.steps, &part.hits) {
+                    (Some(_), None) => path.key("steps"),
+                    (None, Some(_)) => path.key("hits"),
+                    _ => path.clone(),
+                };
+                self.error(&path, error.to_string());
+            }
+        }
+
+        let parameters = [
+            ("level", part.level, &PARAMETER),
+            ("amp_eg_attack", part.amp_eg_attack, &PARAMETER),
+            ("amp_eg_decay", part.amp_eg_decay, &PARAMETER),
+            ("pitch_eg_attack", part.pitch_eg_attack, &PARAMETER),
+            ("pitch_eg_decay", part.pitch_eg_decay, &PARAMETER),
+            ("starting_point", part.starting_point, &PARAMETER),
+            ("length", part.length, &PARAMETER),
+            ("hi_cut", part.hi_cut, &PARAMETER),
+        ];
+        for (name, value, range) in parameters.iter() {
+            if let Some(value) = value {
+                self.check_range(&path.key(name), *value, range);
+            }
+        }
+        // parameters that can also be given in other units
+        let centered = [
+            ("pan", part.pan.as_ref().map(PanDef::value)),
+            (
+                "pitch_eg_int",
+                part.pitch_eg_int.as_ref().map(PitchEgIntDef::value),
+            ),
+        ];
+        for (name, value) in centered {
+            match value {
+                Some(Ok(value)) => self.check_range(&path.key(name), value, &CENTERED_PARAMETER),
+                Some(Err(error)) => self.error(&path.key(name), error.to_string()),
+                None => {}
+            }
+        }
+        match part.speed.as_ref().map(SpeedDef::value) {
+            Some(Ok(speed)) => self.check_speed(&path.key("speed"), speed),
+            Some(Err(error)) => self.error(&path.key("speed"), error.to_string()),
+            None => {}
+        }
+
+        if let Some(motion_sequences) = &part.motion_sequences {
+            self.motion_sequences(&path.key("motion_sequences"), motion_sequences);
+        }
+    }
+
+    fn motion_sequences(&mut self, path: &FieldPath, motion_sequences: &MotionSequencesDef) {
+        let m = motion_sequences;
+        let lanes = [
+            ("level_start", &m.level_start, Some(&PARAMETER)),
+            ("level_end", &m.level_end, Some(&PARAMETER)),
+            ("pan_start", &m.pan_start, Some(&CENTERED_PARAMETER)),
+            ("pan_end", &m.pan_end, Some(&CENTERED_PARAMETER)),
+            ("speed_start", &m.speed_start, None),
+            ("speed_end", &m.speed_end, None),
+            ("amp_eg_attack", &m.amp_eg_attack, Some(&PARAMETER)),
+            ("amp_eg_decay", &m.amp_eg_decay, Some(&PARAMETER)),
+            ("pitch_eg_int", &m.pitch_eg_int, Some(&CENTERED_PARAMETER)),
+            ("pitch_eg_attack", &m.pitch_eg_attack, Some(&PARAMETER)),
+            ("pitch_eg_decay", &m.pitch_eg_decay, Some(&PARAMETER)),
+            ("start_point", &m.start_point, Some(&PARAMETER)),
+            ("length", &m.length, Some(&PARAMETER)),
+            ("hi_cut", &m.hi_cut, Some(&PARAMETER)),
+        ];
+        for (name, motion_sequence, range) in lanes.iter() {
+            let path = path.key(name);
+            let (values, values_path) = match motion_sequence {
+                Some(MotionSequenceDef::Values(values)) => {
+                    if values.len() != MOTION_SEQUENCE_LENGTH {
+                        self.error(
+                            &path,
+                            format!(
+                                "{} values given, a motion sequence has {} values",
+                                values.len(),
+                                MOTION_SEQUENCE_LENGTH
+                            ),
+                        );
+                    }
+                    (values, path.clone())
+                }
+                Some(MotionSequenceDef::Short(short)) => {
+                    let path = path.key("values");
+                    if let Err(error) = short.expand() {
+                        self.error(&path, error.to_string());
+                    }
+                    (&short.values, path)
+                }
+                Some(generator) => {
+                    if let Err(error) = generator.expand(*range) {
+                        self.error(&path, error.to_string());
+                    }
+                    continue;
+                }
+                None => continue,
+            };
+            for (i, value) in values.iter().enumerate() {
+                match range {
+                    Some(range) => self.check_range(&values_path.index(i), *value, range),
+                    None => self.check_speed(&values_path.index(i), *value),
+                }
+            }
+            if range.is_none() {
+                if let Err(error) = check_speed_modes(values) {
+                    self.error(&values_path, error.to_string());
+                }
+            }
+        }
+    }
+}
+
+fn sorted<V>(map: &HashMap<u32, V>) -> Vec<(u32, &V)> {
+    let mut entries: Vec<_> = map.iter().map(|(k, v)| (*k, v)).collect();
+    entries.sort_by_key(|(k, _)| *k);
+    entries
+}
+
+/// Checks the whole configuration, collecting every error found. `source` is the text the
+/// configuration was read from, and is used to find the line and column of each error.
+pub fn validate(volca_sample: &VolcaSample, source: &str) -> Vec<ValidationError> {
+    let mut validator = Validator {
+        source,
+        errors: Vec::new(),
+    };
+    validator.volca_sample(volca_sample);
+    validator.errors
+}
+
+/// Replaces the sample names used by parts with the slot of the sample with that name, collecting
+/// an error for every unknown or duplicate name
+pub fn resolve_sample_names(volca_sample: &mut VolcaSample, source: &str) -> Vec<ValidationError> {
+    let mut validator = Validator {
+        source,
+        errors: Vec::new(),
+    };
+    let root = FieldPath::default();
+
+    let mut slots = HashMap::new();
+    for (index, sample_action) in volca_sample.samples.iter().flat_map(sorted) {
+        if let SampleAction::Sample(SampleDef {
+            name: Some(name), ..
+        }) = sample_action
+        {
+            match slots.get(name.as_str()) {
+                Some(other) => validator.error(
+                    &root.key("samples").key(index).key("name"),
+                    format!("name '{}' is already used by samples.{}", name, other),
+                ),
+                None => {
+                    slots.insert(name.as_str(), index);
+                }
+            }
+        }
+    }
+
+    let mut parts = Vec::new();
+    for (pattern_index, pattern) in volca_sample.patterns.iter_mut().flatten() {
+        for (part_index, part) in pattern.parts.iter_mut() {
+            parts.push(((*pattern_index, *part_index), part));
+        }
+    }
+    parts.sort_by_key(|(indices, _)| *indices);
+    for ((pattern_index, part_index), part) in parts {
+        let name = match &part.sample {
+            Some(SampleRefDef::Name(name)) => name,
+            _ => continue,
+        };
+        match slots.get(name.as_str()) {
+            Some(&slot) => part.sample = Some(SampleRefDef::Slot(slot)),
+            None => {
+                let path = root
+                    .key("patterns")
+                    .key(pattern_index)
+                    .key("parts")
+                    .key(part_index)
+                    .key("sample");
+                validator.error(&path, format!("unknown sample name '{}'", name));
+            }
+        }
+    }
+    validator.errors
+}
+
+/// Fills in the fields each part leaves out from its template, and from the template's own
+/// template, then checks the completed parts. Collects an error for every unknown template or
+/// template that inherits from itself.
+pub fn apply_part_templates(volca_sample: &mut VolcaSample, source: &str) -> Vec<ValidationError> {
+    let mut validator = Validator {
+        source,
+        errors: Vec::new(),
+    };
+    let root = FieldPath::default();
+    let part_templates = volca_sample.part_templates.clone().unwrap_or_default();
+
+    let mut parts = Vec::new();
+    for (pattern_index, pattern) in volca_sample.patterns.iter_mut().flatten() {
+        for (part_index, part) in pattern.parts.iter_mut() {
+            parts.push(((*pattern_index, *part_index), part));
+        }
+    }
+    parts.sort_by_key(|(indices, _)| *indices);
+    for ((pattern_index, part_index), part) in parts {
+        // where the template being applied was named
+        let mut path = root
+            .key("patterns")
+            .key(pattern_index)
+            .key("parts")
+            .key(part_index)
+            .key("template");
+        let mut applied = Vec::new();
+        while let Some(name) = part.template.take() {
+            if applied.contains(&name) {
+                validator.error(&path, format!("template '{}' inherits from itself", name));
+                break;
+            }
+            match part_templates.get(&name) {
+                Some(part_template) => part.inherit(part_template),
+                None => {
+                    validator.error(&path, format!("unknown template '{}'", name));
+                    break;
+                }
+            }
+            path = root.key("part_templates").key(&name).key("template");
+            applied.push(name);
+        }
+    }
+    if validator.errors.is_empty() {
+        validate(volca_sample, source)
+    } else {
+        validator.errors
+    }
+}
+
+// Finds a field in ron, json, yaml or toml source text by walking its path, looking for each
+// segment only inside the value of the one before. Returns the line and column of the deepest
+// segment that was found.
+fn locate(source: &str, path: &FieldPath) -> Option<(usize, usize)> {
+    let (mut found, mut scope, segments) = match ConfigFormat::detect(source) {
+        Some(ConfigFormat::Toml) => {
+            let (start, scope, named) = locate_toml(source, &path.0)?;
+            (Some(start), scope, &path.0[named..])
+        }
+        _ => (None, 0..source.len(), &path.0[..]),
+    };
+    for segment in segments {
+        let start = match segment {
+            Segment::Key(key) => find_key(source, &scope, key),
+            Segment::Index(index) => find_element(source, &scope, *index),
+        };
+        match start {
+            Some((start, value)) => {
+                found = Some(start);
+                scope = value;
+            }
+            None => break,
+        }
+    }
+    found.map(|offset| {
+        let before = &source[..offset];
+        let line = before.matches('\n').count() + 1;
+        let column = before.chars().rev().take_while(|&c| c != '\n').count() + 1;
+        (line, column)
+    })
+}
+
+// A word, quoted string or punctuation character of the source text, at the depth of brackets it
+// is in
+struct Token<'a> {
+    start: usize,
+    text: &'a str,
+    depth: usize,
+}
+
+fn is_identifier(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+fn is_open(token: &Token) -> bool {
+    matches!(token.text, "(" | "[" | "{")
+}
+
+fn is_close(token: &Token) -> bool {
+    matches!(token.text, ")" | "]" | "}")
+}
+
+// Splits the source text in `scope` into tokens, leaving out whitespace and comments
+fn tokens<'a>(source: &'a str, scope: &Range<usize>) -> Vec<Token<'a>> {
+    let mut tokens = Vec::new();
+    let mut depth = 0;
+    let mut position = scope.start;
+    while let Some(c) = source[position..scope.end].chars().next() {
+        let rest = &source[position..scope.end];
+        // quotes and comment signs in the middle of a word are part of it
+        let starts_token = source[..position]
+            .chars()
+            .next_back()
+            .is_none_or(|before| before.is_whitespace() || "([{,:=-".contains(before));
+        let length = match c {
+            '"' | '\'' if starts_token => {
+                let mut escaped = false;
+                rest.char_indices()
+                    .skip(1)
+                    .find(|&(_, d)| {
+                        let end = !escaped && d == c;
+                        escaped = !escaped && d == '\\' && c == '"';
+                        end
+                    })
+                    .map_or(rest.len(), |(i, _)| i + 1)
+            }
+            '#' if starts_token => {
+                position += rest.find('\n').unwrap_or(rest.len());
+                continue;
+            }
+            '/' if rest.starts_with("//") => {
+                position += rest.find('\n').unwrap_or(rest.len());
+                continue;
+            }
+            c if c.is_whitespace() => {
+                position += c.len_utf8();
+                continue;
+            }
+            c if is_identifier(c) || c == '-' || c == '+' => rest
+                .char_indices()
+                .skip(1)
+                .find(|&(_, d)| !is_identifier(d) && d != '.')
+                .map_or(rest.len(), |(i, _)| i),
+            c => c.len_utf8(),
+        };
+        let token = Token {
+            start: position,
+            text: &rest[..length],
+            depth,
+        };
+        if is_close(&token) {
+            depth = depth.saturating_sub(1);
+        }
+        tokens.push(Token { depth, ..token });
+        if "([{".contains(c) {
+            depth += 1;
+        }
+        position += length;
+    }
+    tokens
+}
+
+fn column(source: &str, position: usize) -> usize {
+    position - source[..position].rfind('\n').map_or(0, |i| i + 1)
+}
+
+// Finds `key`, optionally quoted and followed by a colon or equals sign, inside `scope`. Keys
+// nested less deeply are preferred, returning the start of the key and the scope of its value.
+fn find_key(source: &str, scope: &Range<usize>, key: &str) -> Option<(usize, Range<usize>)> {
+    let tokens = tokens(source, scope);
+    let (i, token) = tokens
+        .iter()
+        .enumerate()
+        .filter(|(i, token)| {
+            token.text.trim_matches(|c| c == '"' || c == '\'') == key
+                && tokens
+                    .get(i + 1)
+                    .is_some_and(|next| next.text == ":" || next.text == "=")
+        })
+        .min_by_key(|(_, token)| (token.depth, column(source, token.start)))?;
+    let value_start = tokens[i + 1].start + 1;
+    Some((
+        token.start,
+        value_scope(source, token.start, value_start, scope.end),
+    ))
+}
+
+// The part of the source text taken up by the value of the key at `key_start`, which starts after
+// the separator at `value_start`
+fn value_scope(source: &str, key_start: usize, value_start: usize, end: usize) -> Range<usize> {
+    let tokens = tokens(source, &(value_start..end));
+    let first = match tokens.first() {
+        Some(first) => first,
+        None => return value_start..value_start,
+    };
+    // ron structs and enums can be named before their brackets, like Sample((file: "kick.wav"))
+    let open = match tokens.get(1) {
+        _ if is_open(first) => Some(0),
+        Some(next) if next.text == "(" && next.start == first.start + first.text.len() => Some(1),
+        _ => None,
+    };
+    if let Some(open) = open {
+        let close = tokens[open + 1..]
+            .iter()
+            .find(|token| token.depth == tokens[open].depth && is_close(token));
+        return first.start..close.map_or(end, |close| close.start + 1);
+    }
+    let line_end = source[value_start..end]
+        .find('\n')
+        .map_or(end, |i| value_start + i);
+    if first.start < line_end {
+        return first.start..first.start + first.text.len();
+    }
+    // a yaml block, the lines indented more than the key and list items at its indentation
+    let indent = column(source, key_start);
+    let mut block_end = line_end;
+    for line in source[line_end..end].split('\n').skip(1) {
+        let trimmed = line.trim_start();
+        let line_indent = line.len() - trimmed.len();
+        let in_block = trimmed.is_empty()
+            || trimmed.starts_with('#')
+            || line_indent > indent
+            || (line_indent == indent && trimmed.starts_with('-'));
+        if !in_block {
+            break;
+        }
+        block_end += line.len() + 1;
+    }
+    first.start..block_end.min(end)
+}
+
+// Finds the element at `index` of the list in `scope`, either in brackets or a yaml block list,
+// returning its start and scope
+fn find_element(source: &str, scope: &Range<usize>, index: usize) -> Option<(usize, Range<usize>)> {
+    let tokens = tokens(source, scope);
+    let first = tokens.first()?;
+    if first.text == "-" {
+        let indent = column(source, first.start);
+        let mut items = tokens
+            .iter()
+            .enumerate()
+            .filter(|(_, token)| token.text == "-" && column(source, token.start) == indent)
+            .map(|(i, _)| i);
+        let item = items.nth(index)?;
+        let item_end = items.next().map_or(scope.end, |next| tokens[next].start);
+        let start = tokens
+            .get(item + 1)
+            .filter(|token| token.start < item_end)
+            .map_or(tokens[item].start, |token| token.start);
+        return Some((start, start..item_end));
+    }
+    if first.text != "[" {
+        return None;
+    }
+    let mut element = 0;
+    let mut start = None;
+    let mut end = first.start;
+    for token in tokens[1..].iter() {
+        if token.depth == first.depth {
+            break;
+        }
+        if token.depth == first.depth + 1 && token.text == "," {
+            if start.is_some() {
+                break;
+            }
+            element += 1;
+            continue;
+        }
+        if element == index {
+            start = start.or(Some(token.start));
+            end = token.start + token.text.len();
+        }
+    }
+    start.map(|start| (start, start..end))
+}
+
+// Toml tables and dotted keys name the full path of their values. Returns the start of the line
+// that names the most segments of `path`, the scope of its value and how many segments it names.
+fn locate_toml(source: &str, path: &[Segment]) -> Option<(usize, Range<usize>, usize)> {
+    let dotted_keys = |keys: &str| -> Vec<Segment> {
+        keys.split('.')
+            .map(|key| Segment::Key(key.trim().trim_matches('"').to_string()))
+            .collect()
+    };
+    let mut table = Vec::new();
+    let mut array_lengths: HashMap<String, usize> = HashMap::new();
+    let mut best: Option<(usize, Range<usize>, usize)> = None;
+    let mut next_line = 0;
+    for line in source.split('\n') {
+        let line_start = next_line;
+        let start = line_start + line.len() - line.trim_start().len();
+        next_line += line.len() + 1;
+        let body_start = next_line.min(source.len());
+        let body_end = source[body_start..]
+            .find("\n[")
+            .map_or(source.len(), |i| body_start + i);
+        let trimmed = line.trim();
+        let (keys, scope) = if let Some(header) = trimmed.strip_prefix("[[") {
+            let name = header.split("]]").next().unwrap_or_default();
+            let length = array_lengths.entry(name.trim().to_string()).or_insert(0);
+            table = dotted_keys(name);
+            table.push(Segment::Index(*length));
+            *length += 1;
+            (table.clone(), body_start..body_end)
+        } else if let Some(header) = trimmed.strip_prefix('[') {
+            table = dotted_keys(header.split(']').next().unwrap_or_default());
+            (table.clone(), body_start..body_end)
+        } else if let Some(equals) = line.find('=').filter(|_| !trimmed.starts_with('#')) {
+            let mut keys = table.clone();
+            keys.extend(dotted_keys(&line[..equals]));
+            let value_start = line_start + equals + 1;
+            (keys, value_scope(source, start, value_start, source.len()))
+        } else {
+            continue;
+        };
+
+        // tables can also name the variants of enums, like Sample, which the path leaves out
+        let mut named = 0;
+        for key in keys.iter() {
+            if path.get(named) == Some(key) {
+                named += 1;
+            } else if !matches!(key, Segment::Key(name) if name.starts_with(char::is_uppercase)) {
+                named = 0;
+                break;
+            }
+        }
+        if named > best.as_ref().map_or(0, |(_, _, best)| *best) {
+            best = Some((start, scope, named));
+        }
+    }
+    best
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_validate_ron() -> anyhow::Result<()> {
+        let ron_data = r#"#![enable(implicit_some)]
+VolcaSample(
+    default_compression: 20,
+    samples: {
+        0: Sample((
+            file: "kick.wav",
+            compression: 4,
+        )),
+        10: Slices((file: "break.wav", count: 16)),
+        20: Erase,
+        95: Slices((file: "break.wav", count: 8)),
+    },
+    patterns: {
+        3: (
+            parts: {
+                7: (
+                    sample: 120,
+                    steps: "x... x... x... x..",
+                    level: 200,
+                    speed: 100,
+                    motion_sequences: (
+                        pan_start: [1, 8, 16, 24, 0, 40, 48, 56, 64, 72, 80, 88, 96, 104, 112, 120],
+                        level_end: [1, 2, 3],
+                        hi_cut: "ramp(0)",
+                        pan_end: (values: [1, 200], mode: repeat),
+                        speed_end: [129, 137, 145, 64, 161, 169, 177, 185, 193, 201, 209, 217, 225, 233, 241, 249],
+                    ),
+                ),
+                8: (sample: 0, steps: [1, 0, 0, 0, 1, 0, 0, 0, 1, 0, 0, 0, 2, 0, 0, 0], pan: "L70"),
+            },
+        ),
+        12: (parts: {}),
+    },
+)
+"#;
+        let volca_sample = ron::de::from_str::<VolcaSample>(ron_data)?;
+        let errors: Vec<String> = validate(&volca_sample, ron_data)
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+        assert_eq!(
+            errors,
+            vec![
+                "default_compression: value 20 is out of range 8-16 (line 3, column 5)",
+                "samples.0.compression: value 4 is out of range 8-16 (line 7, column 13)",
+                "samples.20: sample slot 20 is already used by samples.10 (line 10, column 9)",
+                "samples.95: sample slot 100 is out of range 0-99 (line 11, column 9)",
+                "patterns.3.parts.7.sample: value 120 is out of range 0-99 (line 17, column 21)",
+                "patterns.3.parts.7.steps: 15 steps given, a pattern has 16 steps (line 18, column 21)",
+                "patterns.3.parts.7.level: value 200 is out of range 0-127 (line 19, column 21)",
+                "patterns.3.parts.7.speed: value 100 is out of range, valid values are 40-88 (semitone) or 129-255 (continuous) (line 20, column 21)",
+                "patterns.3.parts.7.motion_sequences.level_end: 3 values given, a motion sequence has 16 values (line 23, column 25)",
+                "patterns.3.parts.7.motion_sequences.pan_start[4]: value 0 is out of range 1-127 (line 22, column 51)",
+                "patterns.3.parts.7.motion_sequences.pan_end.values[1]: value 200 is out of range 1-127 (line 25, column 47)",
+                "patterns.3.parts.7.motion_sequences.speed_end: semitone and continuous speeds can't be mixed, the sequence is continuous (129-255) but [3] is semitone (40-88) (line 26, column 25)",
+                "patterns.3.parts.7.motion_sequences.hi_cut: ramp takes 2 arguments (from, to), 1 given (line 24, column 25)",
+                "patterns.3.parts.8.steps: invalid step 2, use 1 or true for on and 0 or false for off (line 29, column 32)",
+                "patterns.3.parts.8.pan: invalid pan 'L70', use C, L1-L63 or R1-R63 (line 29, column 89)",
+                "patterns.12: value 12 is out of range 0-9 (line 32, column 9)",
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_validate_json_yaml_and_toml() -> anyhow::Result<()> {
+        let json_data = r#"{
+  "samples": {
+    "3": { "Sample": { "file": "kick.wav", "compression": 17 } }
+  },
+  "patterns": {
+    "0": { "parts": { "1": { "sample": 0, "hits": [], "pan": 0 } } }
+  }
+}"#;
+        let volca_sample = serde_json::from_str::<VolcaSample>(json_data)?;
+        let errors = validate(&volca_sample, json_data);
+        assert_eq!(errors.len(), 2);
+        assert_eq!(errors[0].path, "samples.3.compression");
+        assert_eq!(errors[0].location, Some((3, 44)));
+        assert_eq!(errors[1].path, "patterns.0.parts.1.pan");
+        assert_eq!(errors[1].location, Some((6, 55)));
+
+        let yaml_data = "patterns:\n  0:\n    parts:\n      1:\n        sample: 0\n        hits: []\n        pan: 0\n";
+        let volca_sample = serde_yaml::from_str::<VolcaSample>(yaml_data)?;
+        let errors = validate(&volca_sample, yaml_data);
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].location, Some((7, 9)));
+
+        let toml_data = "[samples.3.Sample]\nfile = \"kick.wav\"\ncompression = 17\n\n[patterns.0.parts.1]\nsample = 0\nhits = []\npan = 0\n";
+        let volca_sample = toml::from_str::<VolcaSample>(toml_data)?;
+        let errors = validate(&volca_sample, toml_data);
+        assert_eq!(errors.len(), 2);
+        assert_eq!(errors[0].location, Some((3, 1)));
+        assert_eq!(errors[1].location, Some((8, 1)));
+
+        // fields are only looked for inside the value of the field before them
+        let yaml_data = r#"
+samples:
+  0:
+    Sample:
+      file: kick.wav
+      start:
+        seconds: -0.5
+  1:
+    Slices:
+      file: break.wav
+      markers: [{ frames: 0 }, { milliseconds: -1 }]
+sample_dirs:
+  - files: "drums/*.wav"
+  - files: "perc/*.wav"
+    slot: 200
+patterns:
+  0:
+    parts:
+      0: { sample: 0, hits: [], level: 0 }
+"#;
+        let volca_sample = serde_yaml::from_str::<VolcaSample>(yaml_data)?;
+        let errors: Vec<String> = validate(&volca_sample, yaml_data)
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+        assert_eq!(
+            errors,
+            vec![
+                "samples.0.start: value -0.5 must be at least 0 (line 6, column 7)",
+                "samples.1.markers[1]: value -1 must be at least 0 (line 11, column 32)",
+                "sample_dirs[1].slot: value 200 is out of range 0-99 (line 15, column 5)",
+            ]
+        );
+
+        let toml_data = "[[sample_dirs]]\nfiles = \"drums/*.wav\"\n\n[[sample_dirs]]\nfiles = \"perc/*.wav\"\nslot = 200\n";
+        let volca_sample = toml::from_str::<VolcaSample>(toml_data)?;
+        let errors = validate(&volca_sample, toml_data);
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].location, Some((6, 1)));
+        Ok(())
+    }
+
+    #[test]
+    fn test_resolve_sample_names() -> anyhow::Result<()> {
+        let ron_data = r#"#![enable(implicit_some)]
+VolcaSample(
+    samples: {
+        0: Sample((file: "kick.wav", name: "kick")),
+        1: Sample((file: "snare.wav", name: "snare")),
+        2: Sample((file: "kick2.wav", name: "kick")),
+    },
+    patterns: {
+        0: (parts: {
+            0: (sample: "kick", hits: []),
+            1: (sample: 1, hits: []),
+            2: (sample: "hat", hits: []),
+        }),
+    },
+)
+"#;
+        let mut volca_sample = ron::de::from_str::<VolcaSample>(ron_data)?;
+        assert!(validate(&volca_sample, ron_data).is_empty());
+        let errors: Vec<String> = resolve_sample_names(&mut volca_sample, ron_data)
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+        assert_eq!(
+            errors,
+            vec![
+                "samples.2.name: name 'kick' is already used by samples.0 (line 6, column 39)",
+                "patterns.0.parts.2.sample: unknown sample name 'hat' (line 12, column 17)",
+            ]
+        );
+        let parts = &volca_sample.patterns.as_ref().unwrap()[&0].parts;
+        assert_eq!(parts[&0].sample, Some(SampleRefDef::Slot(0)));
+        assert_eq!(parts[&1].sample, Some(SampleRefDef::Slot(1)));
+        Ok(())
+    }
+
+    #[test]
+    fn test_apply_part_templates() -> anyhow::Result<()> {
+        let ron_data = r#"#![enable(implicit_some)]
+VolcaSample(
+    part_templates: {
+        "base": (sample: 1, steps: "x... x... x... x...", level: 100, reverb: on),
+        "soft": (
+            template: "base",
+            level: 40,
+            motion_sequences: (level_start: "hold(40)", level_end: "hold(40)"),
+        ),
+    },
+    patterns: {
+        0: (
+            parts: {
+                0: (template: "soft", hits: [2], motion_sequences: (level_end: "hold(60)")),
+                1: (template: "base", sample: 2),
+                2: (sample: 3, steps: "x... x... x... x..."),
+            },
+        ),
+    },
+)
+"#;
+        let mut volca_sample = ron::de::from_str::<VolcaSample>(ron_data)?;
+        assert!(validate(&volca_sample, ron_data).is_empty());
+        assert!(apply_part_templates(&mut volca_sample, ron_data).is_empty());
+        let parts = &volca_sample.patterns.as_ref().unwrap()[&0].parts;
+        assert_eq!(parts[&0].template, None);
+        assert_eq!(parts[&0].sample, Some(SampleRefDef::Slot(1)));
+        assert_eq!(parts[&0].steps, None);
+        assert_eq!(parts[&0].hits, Some(vec![2]));
+        assert_eq!(parts[&0].level, Some(40));
+        assert_eq!(parts[&0].reverb, Some(ToggleDef::On));
+        let motion_sequences = parts[&0].motion_sequences.as_ref().unwrap();
+        let hold = |value: &str| Some(MotionSequenceDef::Generator(format!("hold({})", value)));
+        assert_eq!(motion_sequences.level_start, hold("40"));
+        assert_eq!(motion_sequences.level_end, hold("60"));
+        assert_eq!(parts[&1].sample, Some(SampleRefDef::Slot(2)));
+        assert_eq!(parts[&1].level, Some(100));
+        assert_eq!(parts[&2].level, None);
+
+        let ron_data = r#"#![enable(implicit_some)]
+VolcaSample(
+    part_templates: {
+        "a": (template: "b"),
+        "b": (template: "a"),
+        "c": (template: "d"),
+        "e": (level: 40),
+    },
+    patterns: {
+        0: (
+            parts: {
+                0: (template: "a"),
+                1: (template: "c"),
+                2: (template: "f"),
+            },
+        ),
+        1: (parts: { 0: (template: "e") }),
+    },
+)
+"#;
+        let mut volca_sample = ron::de::from_str::<VolcaSample>(ron_data)?;
+        assert!(validate(&volca_sample, ron_data).is_empty());
+        let errors: Vec<String> = apply_part_templates(&mut volca_sample, ron_data)
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+        assert_eq!(
+            errors,
+            vec![
+                "part_templates.b.template: template 'a' inherits from itself (line 5, column 15)",
+                "part_templates.c.template: unknown template 'd' (line 6, column 15)",
+                "patterns.0.parts.2.template: unknown template 'f' (line 14, column 21)",
+            ]
+        );
+
+        // the templates are applied, but the part is still missing its sample and steps
+        let mut volca_sample = ron::de::from_str::<VolcaSample>(ron_data)?;
+        volca_sample.patterns.as_mut().unwrap().remove(&0);
+        let errors: Vec<String> = apply_part_templates(&mut volca_sample, ron_data)
+            .iter()
+            .map(|error| format!("{}: {}", error.path, error.message))
+            .collect();
+        assert_eq!(
+            errors,
+            vec![
+                "patterns.1.parts.0: sample is required",
+                "patterns.1.parts.0: either steps or hits is required",
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_sample_slots() {
+        let slices = |count| {
+            SampleAction::Slices(SlicesDef {
+                file: String::from("break.wav"),
+                count: Some(count),
+                markers: None,
+                compression: None,
+                channels: None,
+                resample: None,
+                trim_silence: None,
+                normalize: None,
+            })
+        };
+        let errors = |samples: Vec<(u32, SampleAction)>| {
+            let volca_sample = VolcaSample {
+                samples: Some(samples.into_iter().collect()),
+                ..Default::default()
+            };
+            validate(&volca_sample, "")
+        };
+
+        assert!(errors(vec![(10, slices(16)), (26, SampleAction::Erase)]).is_empty());
+        assert_eq!(
+            errors(vec![(10, slices(16)), (25, SampleAction::Erase)])[0].path,
+            "samples.25"
+        );
+        assert_eq!(errors(vec![(90, slices(16))]).len(), 1);
+        assert_eq!(errors(vec![(0, slices(0))]).len(), 1);
+    }
+}